@@ -1,5 +1,7 @@
 importScripts('gomoku.js');
 
+let engine = undefined;
+
 function load_wasm() {
     let msg = 'This demo requires a current version of Firefox (e.g., 79.0)';
     if (typeof SharedArrayBuffer !== 'function') {
@@ -30,20 +32,60 @@ onmessage = message => {
 
     self.onmessage = async event => {
         // This will queue further commands up until the module is fully initialised:
-        await initialised;
+        const exports = await initialised;
+
+        const { Engine, make_move, pass_move, rank_of, file_of } = wasm_bindgen;
 
-        const { think_and_move, make_move, rank_of, file_of, foul_moves, check_wld_already } = wasm_bindgen;
+        if (engine === undefined) {
+            engine = new Engine();
+            // Progress is tagged apart from the answers to commands
+            engine.set_progress_callback(result => postMessage({ type: 'progress', result }));
+        }
 
         let funcs = {
-            'think_and_move': think_and_move,
+            'think_and_move': (ms, tl) => engine.think_and_move(ms, tl),
+            'think_timed': (ms, time_left, increment, moves_to_go) => engine.think_timed(ms, time_left, increment, moves_to_go),
+            'think_fixed': (ms, depth, nodes) => engine.think_fixed(ms, depth, nodes),
+            'swap_move': (ms, swap2) => engine.swap_move(ms, swap2),
+            'analyze': (ms, tl, n) => engine.analyze(ms, tl, n),
+            'analyze_infinite': (ms, n) => engine.analyze_infinite(ms, n),
+            // The caller stops a running search with Atomics.store(new Uint8Array(memory.buffer), ptr, 1);
+            // the next search command lowers the flag again
+            'stop_flag': () => ({ memory: exports.memory, ptr: engine.stop_flag_ptr() }),
+            'clear_stop': () => engine.clear_stop(),
             'make_move': make_move,
             'rank_of': rank_of,
             'file_of': file_of,
-            'foul_moves': foul_moves,
-            'check_wld_already': check_wld_already,
+            'pass_move': pass_move,
+            'setup_moves': (black, white, black_to_move) => engine.setup_moves(black, white, black_to_move),
+            'parse_moves': (s) => engine.parse_moves(s),
+            'format_moves': (ms) => engine.format_moves(ms),
+            'load_psq': (s) => engine.load_psq(s),
+            'save_psq': (ms) => engine.save_psq(ms),
+            'load_lib': (data) => engine.load_lib(new Uint8Array(data)),
+            'save_lib': (tree) => engine.save_lib(tree),
+            'load_rif': (xml) => engine.load_rif(xml),
+            'load_sgf': (s) => engine.load_sgf(s),
+            'save_sgf': (tree) => engine.save_sgf(tree),
+            'position_moves': (s) => engine.position_moves(s),
+            'position_string': (ms) => engine.position_string(ms),
+            'foul_moves': (ms) => engine.foul_moves(ms),
+            'fouls': (ms) => engine.fouls(ms),
+            'check_wld_already': (ms) => engine.check_wld_already(ms),
+            'game_end': (ms) => engine.game_end(ms),
+            'start_opening': (rule) => engine.start_opening(rule),
+            'opening_state': () => engine.opening_state(),
+            'opening_play': (m) => engine.opening_play(m),
+            'opening_declare_fifths': (n) => engine.opening_declare_fifths(n),
+            'opening_swap': (swap) => engine.opening_swap(swap),
+            'opening_propose_fifths': (ms) => engine.opening_propose_fifths(ms),
+            'opening_choose_fifth': (m) => engine.opening_choose_fifth(m),
+            'set_hash_mb': (mb) => engine.set_hash_mb(mb),
+            'set_rule': (rule) => engine.set_rule(rule),
+            'set_board_side': (side) => engine.set_board_side(side),
         };
         const { func_name, args } = JSON.parse(event.data);
-        result = funcs[func_name](...Object.values(args));
-        postMessage(result);
+        const result = funcs[func_name](...Object.values(args));
+        postMessage({ type: 'result', result });
     };
 }
\ No newline at end of file
@@ -14,7 +14,7 @@ set -ex
 
 # RUSTFLAGS='-C target-feature=+atomics,+bulk-memory,+mutable-globals' \
 RUSTFLAGS='-C target-feature=+atomics,+bulk-memory,+mutable-globals -C link-arg=--max-memory=268435456' \
-  cargo build --target wasm32-unknown-unknown --release -Z build-std=std,panic_abort
+  cargo build --lib --target wasm32-unknown-unknown --release -Z build-std=std,panic_abort
 
 # Note the usage of `--target no-modules` here which is required for passing
 # the memory import to each wasm module.
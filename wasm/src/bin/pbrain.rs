@@ -0,0 +1,284 @@
+// Gomocup / Piskvork brain protocol front-end.
+// See https://plastovicka.github.io/protocl2en.htm for the command reference.
+
+use std::cmp;
+use std::io::{self, BufRead, Write};
+
+use gomoku::timeman::TimeControl;
+use gomoku::types::*;
+use gomoku::Engine;
+
+const DEFAULT_TIMEOUT_TURN: i32 = 5000;
+
+struct Brain {
+    engine: Engine,
+    moves: Vec<Move>,
+    started: bool,
+    timeout_turn: i32,
+    timeout_match: i32,
+    time_left: i32,
+}
+
+impl Brain {
+    fn new() -> Brain {
+        let mut engine = Engine::new();
+        engine.set_rule(Rule::Freestyle);
+
+        engine.set_progress(Some(Box::new(|r: &SearchResult| {
+            let mut stdout = io::stdout();
+            let _ = writeln!(stdout, "MESSAGE depth {}-{} ev {} n {} tm {:.0}", r.depth, r.seldepth, r.score, r.nodes, r.elapsed);
+            let _ = stdout.flush();
+        })));
+
+        Self {
+            engine,
+            moves: Vec::new(),
+            started: false,
+            timeout_turn: DEFAULT_TIMEOUT_TURN,
+            timeout_match: 0,
+            time_left: i32::MAX,
+        }
+    }
+
+    // Gomocup rule bits: 1 exactly five, 4 renju, 8 caro; 0 is freestyle
+    fn set_rule(&mut self, bits: i64) {
+        let rule = if bits & 4 != 0 {
+            Rule::Renju
+        } else if bits & 8 != 0 {
+            Rule::Caro
+        } else if bits & 1 != 0 {
+            Rule::Standard
+        } else {
+            Rule::Freestyle
+        };
+
+        if rule != self.engine.rule() {
+            self.engine.set_rule(rule);
+            self.moves.clear();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.engine.reset();
+        self.moves.clear();
+    }
+
+    fn time_control(&self) -> TimeControl {
+        TimeControl {
+            time_left: if self.timeout_match > 0 {self.time_left} else {i32::MAX},
+            increment: 0,
+            moves_to_go: 0,
+            // 0 asks for the fastest possible reply
+            move_time: cmp::max(self.timeout_turn, 1),
+        }
+    }
+
+    fn parse_move(&self, s: &str) -> Option<Move> {
+        let xy: Vec<i32> = s.split(',').take(2).filter_map(|x| x.trim().parse().ok()).collect();
+        if xy.len() != 2 || xy.iter().any(|x| *x < 0 || *x >= self.engine.board_side()) {
+            return None;
+        }
+
+        // Piskvork sends the column first
+        Some(_make_move(&xy[1], &xy[0]))
+    }
+
+    fn play(&mut self, m: Move) -> bool {
+        if !self.engine.board().is_empty(m) {
+            return false;
+        }
+        self.engine.do_move(m);
+        self.moves.push(m);
+        true
+    }
+
+    // Takes back `m` if it is the last stone, along with any pass a set-up position ended in
+    fn take_back(&mut self, m: Move) -> bool {
+        let passes: usize = self.moves.iter().rev().take_while(|x| **x == MOVE_PASS).count();
+        if self.moves.len() <= passes || self.moves[self.moves.len() - passes - 1] != m {
+            return false;
+        }
+
+        for _ in 0..passes + 1 {
+            self.engine.undo_move();
+            self.moves.pop();
+        }
+        true
+    }
+
+    fn think_and_play(&mut self) -> String {
+        let m = self.engine.think_with_clock(&self.time_control()).best_move;
+        self.play(m);
+        format!("{},{}", _file_of(m), _rank_of(m))
+    }
+
+    fn info(&mut self, key: &str, value: &str) {
+        let v: i64 = match value.trim().parse() {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+
+        match key.to_ascii_lowercase().as_str() {
+            "timeout_turn" => self.timeout_turn = v as i32,
+            "timeout_match" => self.timeout_match = v as i32,
+            "time_left" => self.time_left = v as i32,
+            "rule" => self.set_rule(v),
+            // Leave half of the memory limit for the board and the process itself
+            "max_memory" if v > 0 => self.engine.set_hash_mb(((v / 2) >> 20) as usize),
+            _ => {}
+        }
+    }
+
+    // Reads the stone list of a BOARD command. The stones need not come in move order: the position is
+    // set up by colour, we being Black unless the opponent has more stones. We are to move, so we never
+    // have more stones than the opponent.
+    fn board<I: Iterator<Item = String>>(&mut self, lines: &mut I) -> Result<(), String> {
+        let mut own: Vec<Move> = Vec::new();
+        let mut opponent: Vec<Move> = Vec::new();
+        // Stones of a winning line in continuous games, of either colour
+        let mut either: Vec<Move> = Vec::new();
+
+        for line in lines {
+            let line = line.trim().to_string();
+            if line.eq_ignore_ascii_case("DONE") {
+                break;
+            }
+
+            let field: Vec<&str> = line.split(',').collect();
+            let m = match (field.len(), self.parse_move(&line)) {
+                (3, Some(m)) => m,
+                _ => return Err(format!("ERROR invalid board line: {}", line)),
+            };
+            match field[2].trim() {
+                "1" => own.push(m),
+                "2" => opponent.push(m),
+                "3" => either.push(m),
+                _ => return Err(format!("ERROR invalid board line: {}", line)),
+            }
+        }
+
+        // Winning-line stones go to whichever colour is short of stones
+        for m in either.into_iter() {
+            if own.len() < opponent.len() {own.push(m)} else {opponent.push(m)}
+        }
+
+        if own.len() > opponent.len() {
+            return Err(String::from("ERROR more own stones than the opponent's with us to move"));
+        }
+
+        self.restart();
+
+        let (black, white) = if opponent.len() > own.len() {(&opponent, &own)} else {(&own, &opponent)};
+        let side_to_move = if opponent.len() > own.len() {Color::White} else {Color::Black};
+        if !self.engine.setup(black, white, side_to_move) {
+            return Err(String::from("ERROR stones must be on the board and not overlap"));
+        }
+        self.moves = _setup_moves(black, white, side_to_move);
+
+        Ok(())
+    }
+}
+
+fn main() {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    let mut lines = stdin.lock().lines().map_while(Result::ok);
+    let mut brain = Brain::new();
+
+    while let Some(line) = lines.next() {
+        let line = line.trim().to_string();
+        let (cmd, arg) = match line.find(' ') {
+            Some(i) => (line[..i].to_ascii_uppercase(), line[i + 1..].trim().to_string()),
+            None => (line.to_ascii_uppercase(), String::new()),
+        };
+
+        let reply: Option<String> = match cmd.as_str() {
+            "" => None,
+            "START" => match arg.parse::<i32>() {
+                Ok(size) if brain.engine.set_board_side(size) => {
+                    brain.restart();
+                    brain.started = true;
+                    Some(String::from("OK"))
+                },
+                _ => Some(format!("ERROR only boards from {0}x{0} to {1}x{1} are supported", BOARD_SIDE_MIN, BOARD_SIDE_MAX)),
+            },
+            "RESTART" => {
+                brain.restart();
+                Some(String::from("OK"))
+            },
+            "INFO" => {
+                let mut kv = arg.splitn(2, ' ');
+                if let (Some(k), Some(v)) = (kv.next(), kv.next()) {
+                    brain.info(k, v);
+                }
+                None
+            },
+            _ if !brain.started && cmd != "ABOUT" && cmd != "END" => Some(String::from("ERROR send START first")),
+            "BEGIN" => {
+                if brain.moves.is_empty() {
+                    Some(brain.think_and_play())
+                } else {
+                    Some(String::from("ERROR the board is not empty"))
+                }
+            },
+            "TURN" => match brain.parse_move(&arg) {
+                Some(m) if brain.play(m) => Some(brain.think_and_play()),
+                _ => Some(format!("ERROR invalid move: {}", arg)),
+            },
+            "BOARD" => match brain.board(&mut lines) {
+                Ok(()) => Some(brain.think_and_play()),
+                Err(e) => Some(e),
+            },
+            "TAKEBACK" => match brain.parse_move(&arg) {
+                Some(m) if brain.take_back(m) => Some(String::from("OK")),
+                _ => Some(format!("ERROR cannot take back: {}", arg)),
+            },
+            "ABOUT" => Some(format!("name=\"gomoku\", version=\"{}\"", env!("CARGO_PKG_VERSION"))),
+            "END" => break,
+            _ => Some(String::from("UNKNOWN")),
+        };
+
+        if let Some(r) = reply {
+            writeln!(stdout, "{}", r).unwrap();
+            stdout.flush().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board_lines(lines: &[&str]) -> impl Iterator<Item = String> {
+        lines.iter().map(|l| l.to_string()).collect::<Vec<String>>().into_iter()
+    }
+
+    #[test]
+    fn board_with_more_own_stones_is_rejected() {
+        let mut brain: Brain = Brain::new();
+
+        assert!(brain.board(&mut board_lines(&["7,7,1", "8,8,1", "7,8,2", "DONE"])).is_err());
+        assert!(brain.board(&mut board_lines(&["7,7,1", "7,8,2", "DONE"])).is_ok());
+        assert_eq!(brain.moves, vec![_make_move(&7, &7), _make_move(&8, &7)]);
+        assert!(brain.board(&mut board_lines(&["7,7,2", "8,8,2", "7,8,1", "DONE"])).is_ok());
+        assert!(brain.engine.board().side_to_move == Color::White);
+    }
+
+    #[test]
+    fn takeback_looks_past_a_trailing_pass() {
+        let mut brain: Brain = Brain::new();
+        let black: Vec<Move> = vec![_make_move(&7, &7), _make_move(&8, &8)];
+        let white: Vec<Move> = vec![_make_move(&7, &8)];
+
+        assert!(brain.engine.setup(&black, &white, Color::Black));
+        brain.moves = _setup_moves(&black, &white, Color::Black);
+        assert_eq!(brain.moves.last(), Some(&MOVE_PASS));
+
+        assert!(!brain.take_back(white[0]));
+        assert!(brain.take_back(black[1]));
+        assert_eq!(brain.moves, vec![black[0], white[0]]);
+        assert!(brain.engine.board().is_empty(black[1]));
+        assert!(brain.engine.board().side_to_move == Color::Black);
+        assert!(brain.take_back(white[0]));
+    }
+}
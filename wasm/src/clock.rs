@@ -0,0 +1,27 @@
+// Monotonic time source in milliseconds, shared by the browser and native builds.
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+    #[no_mangle]
+    #[used]
+    static performance:web_sys::Performance;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn now() -> f64 {
+    performance.now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now() -> f64 {
+    use std::sync::OnceLock;
+    use std::time::Instant;
+
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+    EPOCH.get_or_init(Instant::now).elapsed().as_secs_f64() * 1000.0
+}
@@ -1,550 +1,1264 @@
 use wasm_bindgen::prelude::*;
 use std::cmp;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 // mod pool;
 
-static mut FUTILITY_MOVE_COUNT: [[i32; DEPTH_NUM as usize]; 2] = [[0; DEPTH_NUM as usize]; 2];
-static mut REDUCTION: [[[Depth; MOVE_SIZE as usize]; DEPTH_NUM as usize]; 2] = [[[0; MOVE_SIZE as usize]; DEPTH_NUM as usize]; 2];
-
-mod types;
+mod clock;
+pub mod notation;
+pub mod opening;
+pub mod position;
+pub mod psq;
+pub mod renlib;
+pub mod rif;
+pub mod sgf;
+pub mod swap;
+pub mod timeman;
+pub mod types;
+use swap::*;
+use timeman::*;
 use types::*;
 
-#[wasm_bindgen(start)]
-unsafe fn start() {
-    FUTILITY_MOVE_COUNT[0][0] = -2;
-    FUTILITY_MOVE_COUNT[1][0] = -12;
-
-    for i in 1..DEPTH_NUM as usize {
-        FUTILITY_MOVE_COUNT[0][i] = cmp::min(((5 as f64) * (i as f64).sqrt() - (2 as f64)).round() as i32, 8);
-        FUTILITY_MOVE_COUNT[1][i] = cmp::min(((15 as f64) * (i as f64).sqrt() - (12 as f64)).round() as i32, 32);
-        for mc in 1..MOVE_SIZE as usize {
-            let r = (i as f64).ln() * (mc as f64).ln() / 2.0;
-            REDUCTION[0][i][mc] = r.round() as Depth;
-            REDUCTION[1][i][mc] = cmp::max(REDUCTION[0][i][mc] - 1, 0 as Depth);
-        }
-    }
-    BOARD = Some(Board::new());
-}
-
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
     unsafe fn log(s: &str);
-    #[no_mangle]
-    #[used]
-    static performance:web_sys::Performance;
 }
 
-static mut PERF_START: f64 = 0.0;
-static mut TIME_LIMIT: i32 = 30;
+// Receives the result of each completed depth, see `set_progress_callback`
+pub type ProgressCallback = Box<dyn FnMut(&SearchResult)>;
 
 #[wasm_bindgen]
-pub unsafe fn think_and_move(ms: JsValue, tl: i32) -> Result<Move, JsValue> {
-    PERF_START = performance.now();
-    TIME_LIMIT = tl;
-    let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
+pub struct Engine {
+    board: Box<Board>,
+    ply: Depth,
+    ply_max: Depth,
+    search_stack: SearchStack,
+    root_bests: Vec<RootExtMove>,
+    counter_moves: CounterMoveHistory,
+    tt: TranspositionTable,
+    futility_move_count: [[i32; DEPTH_NUM as usize]; 2],
+    reduction: [[[Depth; MOVE_SIZE as usize]; DEPTH_NUM as usize]; 2],
+    nodes: u64,
+    perf_start: f64,
+    time_limit: i32,
+    time_manager: Option<TimeManager>,
+    depth_limit: Depth,
+    node_limit: u64,
+    stop: Arc<AtomicBool>,
+    progress: Option<ProgressCallback>,
+    multi_pv: usize,
+    root_excluded: Vec<Move>,
+    root_lines: Vec<RootExtMove>,
+    opening: Option<opening::Opening>,
+    // Moves reaching the last set-up position
+    setup: Vec<Move>,
+}
 
-    reset_search();
-    BOARD.as_mut().unwrap().reset();
-    
-    for m in moves.iter() {
-        BOARD.as_mut().unwrap().do_move(*m);
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    let mut skip_search: bool = false;
-    let mut rem: RootExtMove = RootExtMove::new();
-    {
-        let mut mg: MoveGen = MoveGen::new(());
-        let mut em: ExtMove;
-        let mut offset: Score = -1;
+#[wasm_bindgen]
+impl Engine {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Engine {
+        let mut engine = Engine {
+            board: Box::new(Board::new()),
+            ply: 0,
+            ply_max: 0,
+            search_stack: [SearchStackElement::new(); STACK_SIZE as usize],
+            root_bests: Vec::new(),
+            counter_moves: [MOVE_NONE; MOVE_CAPACITY as usize],
+            tt: TranspositionTable::new(),
+            futility_move_count: [[0; DEPTH_NUM as usize]; 2],
+            reduction: [[[0; MOVE_SIZE as usize]; DEPTH_NUM as usize]; 2],
+            nodes: 0,
+            perf_start: 0.0,
+            time_limit: 30000,
+            time_manager: None,
+            depth_limit: DEPTH_ITERATIVE_MAX,
+            node_limit: 0,
+            stop: Arc::new(AtomicBool::new(false)),
+            progress: None,
+            multi_pv: 1,
+            root_excluded: Vec::new(),
+            root_lines: Vec::new(),
+            opening: None,
+            setup: Vec::new(),
+        };
+
+        engine.futility_move_count[0][0] = -2;
+        engine.futility_move_count[1][0] = -12;
+
+        for i in 1..DEPTH_NUM as usize {
+            engine.futility_move_count[0][i] = cmp::min((5.0 * (i as f64).sqrt() - 2.0).round() as i32, 8);
+            engine.futility_move_count[1][i] = cmp::min((15.0 * (i as f64).sqrt() - 12.0).round() as i32, 32);
+            for mc in 1..MOVE_SIZE as usize {
+                let r = (i as f64).ln() * (mc as f64).ln() / 2.0;
+                engine.reduction[0][i][mc] = r.round() as Depth;
+                engine.reduction[1][i][mc] = cmp::max(engine.reduction[0][i][mc] - 1, 0 as Depth);
+            }
+        }
 
-        // unsafe {
-        //     TT.new_search();
-        // }
+        engine
+    }
 
-        if !skip_search && moves.len() == 0 {
-            rem.score = 0;
-            rem.depth = 1 as Depth;
-            update_pv2(Some(&mut rem.pv), make_move(7, 7));
-            skip_search = true;
-        }
+    pub fn think_and_move(&mut self, ms: JsValue, tl: i32) -> Result<JsValue, JsValue> {
+        let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
 
-        if !skip_search && BOARD.as_ref().unwrap().check_wld(&mut offset) != Color::None {
-            em = mg.generate(GenType::WLD);
-            rem.score = em.s;
-            rem.depth = offset as Depth;
-            update_pv2(Some(&mut rem.pv), em.m);
-            skip_search = true;
+        Ok(serde_wasm_bindgen::to_value(&self._think_and_move(&moves, tl))?)
+    }
+
+    // Next Swap1 (`swap2` false) or Swap2 opening step after 0, 3 or (Swap2 only) 5 stones
+    pub fn swap_move(&mut self, ms: JsValue, swap2: bool) -> Result<JsValue, JsValue> {
+        let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
+
+        match self._swap_move(&moves, swap2) {
+            Some(result) => Ok(serde_wasm_bindgen::to_value(&result)?),
+            None => Err(JsValue::from_str("no swap decision is made at this move")),
         }
+    }
 
-        if !skip_search {
-            em = mg.generate(GenType::MAIN);
-            if mg.size() == 1 {
-                rem.score = 0;
-                rem.depth = 1 as Depth;
-                update_pv2(Some(&mut rem.pv), em.m);
-                skip_search = true;
+    // Budgets the move from the game clock: `time_left` and `increment` in milliseconds,
+    // `moves_to_go` 0 when the remaining time is for the rest of the game
+    pub fn think_timed(&mut self, ms: JsValue, time_left: i32, increment: i32, moves_to_go: i32) -> Result<JsValue, JsValue> {
+        let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
+        let tc: TimeControl = TimeControl { time_left, increment, moves_to_go, move_time: 0 };
+
+        self.replay(&moves);
+
+        Ok(serde_wasm_bindgen::to_value(&self.think_with_clock(&tc))?)
+    }
+
+    // Reproducible search bounded by `depth` and/or `nodes` instead of time, 0 for no bound
+    pub fn think_fixed(&mut self, ms: JsValue, depth: Depth, nodes: u32) -> Result<JsValue, JsValue> {
+        let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
+
+        self.replay(&moves);
+
+        Ok(serde_wasm_bindgen::to_value(&self.think_limited(depth, nodes as u64))?)
+    }
+
+    // Searches for at most `tl` seconds and returns the best `n` root moves, best first
+    pub fn analyze(&mut self, ms: JsValue, tl: i32, n: usize) -> Result<JsValue, JsValue> {
+        let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
+
+        Ok(serde_wasm_bindgen::to_value(&self._analyze(&moves, tl, n))?)
+    }
+
+    // Like `analyze`, but without a deadline: the search runs until the stop flag is raised
+    pub fn analyze_infinite(&mut self, ms: JsValue, n: usize) -> Result<JsValue, JsValue> {
+        let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
+
+        self.replay(&moves);
+        self.clear_stop();
+
+        Ok(serde_wasm_bindgen::to_value(&self.think_multi_pv(TIME_INFINITE, n))?)
+    }
+
+    // Address of the stop flag in the wasm memory. The UI thread raises it with
+    // `Atomics.store(new Uint8Array(memory.buffer), ptr, 1)` while the worker is searching;
+    // every search command lowers it again before it starts.
+    pub fn stop_flag_ptr(&self) -> usize {
+        Arc::as_ptr(&self.stop) as usize
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn clear_stop(&self) {
+        self.stop.store(false, Ordering::Relaxed);
+    }
+
+    // `f` is called with a search result after every completed iterative-deepening depth
+    pub fn set_progress_callback(&mut self, f: Option<js_sys::Function>) {
+        self.progress = f.map(|f| Box::new(move |r: &SearchResult| {
+            if let Ok(v) = serde_wasm_bindgen::to_value(r) {
+                let _ = f.call1(&JsValue::NULL, &v);
             }
+        }) as ProgressCallback);
+    }
+
+    pub fn foul_moves(&mut self, ms: JsValue) -> Result<JsValue, JsValue> {
+        let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
+
+        Ok(serde_wasm_bindgen::to_value(&self._foul_moves(&moves))?)
+    }
+
+    // Like `foul_moves`, with the reason and the lines behind every forbidden point
+    pub fn fouls(&mut self, ms: JsValue) -> Result<JsValue, JsValue> {
+        let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
+
+        Ok(serde_wasm_bindgen::to_value(&self._fouls(&moves))?)
+    }
+
+    pub fn clear_hash(&mut self) {
+        self.tt.clear();
+    }
+
+    pub fn board_side(&self) -> i32 {
+        self.board.side()
+    }
+
+    // Starts a new game on a `side` x `side` board; returns false if the side is not supported
+    pub fn set_board_side(&mut self, side: i32) -> bool {
+        if !self.board.set_side(side) {
+            return false;
         }
+        self.tt.clear();
+
+        true
     }
 
-    if skip_search {
-        ROOT_BESTS.push(rem);
-    } else {
-        search();
+    pub fn rule(&self) -> Rule {
+        self.board.rule()
     }
 
-    let best_move = ROOT_BESTS.last().unwrap_unchecked().pv[0];
+    // Also clears the board and the hash, whose entries were scored under the old rule
+    pub fn set_rule(&mut self, rule: Rule) {
+        self.board.set_rule(rule);
+        self.tt.clear();
+    }
 
-    Ok(best_move)
-}
+    pub fn set_hash_mb(&mut self, mb: usize) {
+        self.tt.set_hash_mb(mb);
+    }
+
+    pub fn release_hash(&mut self) {
+        self.tt.release();
+    }
 
-static mut itd: i32 = 0;
+    // Move list, passes included, reaching a set-up position; every `ms` argument accepts it
+    pub fn setup_moves(&mut self, black: JsValue, white: JsValue, black_to_move: bool) -> Result<JsValue, JsValue> {
+        let black: Vec<Move> = serde_wasm_bindgen::from_value(black)?;
+        let white: Vec<Move> = serde_wasm_bindgen::from_value(white)?;
+        let side_to_move: Color = if black_to_move {Color::Black} else {Color::White};
 
-unsafe fn reset_alpha_beta() {
-    PLY = 0;
-    if SEARCH_STACK[0].pv.is_some() {
-        reset_pv(SEARCH_STACK[0].pv.as_mut().unwrap_unchecked());
-    } else {
-        SEARCH_STACK[0].pv = Some([0; STACK_SIZE as usize]);
+        if !self.setup(&black, &white, side_to_move) {
+            return Err(JsValue::from_str("stones must be on the board and not overlap"));
+        }
+
+        Ok(serde_wasm_bindgen::to_value(&_setup_moves(&black, &white, side_to_move))?)
+    }
+
+    // Moves written like "h8i9j10" on the current board
+    pub fn parse_moves(&self, s: &str) -> Result<JsValue, JsValue> {
+        let moves: Vec<Move> = notation::parse_moves(s, self.board.side()).map_err(|e| JsValue::from_str(&e))?;
+
+        Ok(serde_wasm_bindgen::to_value(&moves)?)
+    }
+
+    pub fn format_moves(&self, ms: JsValue) -> Result<String, JsValue> {
+        let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
+
+        Ok(notation::moves_to_string(&moves, self.board.side()))
+    }
+
+    // A Piskvork record with its times and trailing lines, checked on a board resized to the game
+    pub fn load_psq(&mut self, s: &str) -> Result<JsValue, JsValue> {
+        let game: psq::PsqGame = self._load_psq(s).map_err(|e| JsValue::from_str(&e))?;
+
+        Ok(serde_wasm_bindgen::to_value(&game)?)
+    }
+
+    pub fn save_psq(&self, ms: JsValue) -> Result<String, JsValue> {
+        let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
+
+        psq::write_psq(&psq::PsqGame::new(self.board.side(), &moves)).map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Move tree of a RenLib library; the board becomes 15x15
+    pub fn load_lib(&mut self, data: &[u8]) -> Result<JsValue, JsValue> {
+        let root: renlib::LibNode = renlib::read_lib(data, &mut self.board).map_err(|e| JsValue::from_str(&e))?;
+        self.tt.clear();
+
+        Ok(serde_wasm_bindgen::to_value(&root)?)
+    }
+
+    pub fn save_lib(&self, tree: JsValue) -> Result<Vec<u8>, JsValue> {
+        let root: renlib::LibNode = serde_wasm_bindgen::from_value(tree)?;
+
+        Ok(renlib::write_lib(&root))
+    }
+
+    // Players, tournaments and replayed games of a RIF archive; the board becomes 15x15, keeping its rule
+    pub fn load_rif(&mut self, xml: &str) -> Result<JsValue, JsValue> {
+        let db: rif::RifDatabase = rif::RifDatabase::parse(xml, &mut self.board).map_err(|e| JsValue::from_str(&e))?;
+        self.tt.clear();
+
+        Ok(serde_wasm_bindgen::to_value(&db)?)
+    }
+
+    // Variation tree of an SGF record; the board takes the record's size
+    pub fn load_sgf(&mut self, s: &str) -> Result<JsValue, JsValue> {
+        let root: sgf::SgfNode = sgf::read_sgf(s, &mut self.board).map_err(|e| JsValue::from_str(&e))?;
+        self.tt.clear();
+
+        Ok(serde_wasm_bindgen::to_value(&root)?)
+    }
+
+    pub fn save_sgf(&self, tree: JsValue) -> Result<String, JsValue> {
+        let root: sgf::SgfNode = serde_wasm_bindgen::from_value(tree)?;
+
+        Ok(sgf::write_sgf(&root, self.board.side()))
+    }
+
+    // Moves reaching a one-line position, whose rule and size the engine takes
+    pub fn position_moves(&mut self, s: &str) -> Result<JsValue, JsValue> {
+        let moves: Vec<Move> = self.set_position(s).map_err(|e| JsValue::from_str(&e))?;
+
+        Ok(serde_wasm_bindgen::to_value(&moves)?)
+    }
+
+    pub fn position_string(&mut self, ms: JsValue) -> Result<String, JsValue> {
+        let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
+
+        Ok(self._position_string(&moves))
+    }
+
+    pub fn check_wld_already(&mut self, ms: JsValue) -> Result<i32, JsValue> {
+        let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
+
+        Ok(self._check_wld_already(&moves))
+    }
+
+    // Like `check_wld_already`, with the winning five, the foul or the draw reason; null while the game goes on
+    pub fn game_end(&mut self, ms: JsValue) -> Result<JsValue, JsValue> {
+        let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
+
+        Ok(serde_wasm_bindgen::to_value(&self._game_end(&moves))?)
+    }
+
+    // Starts an opening (rif, yamaguchi, taraguchi10 or soosorv8) on the 15x15 board. This and the other
+    // opening calls return the player to decide next, the decision, and the stones and fifth moves so far.
+    pub fn start_opening(&mut self, rule: &str) -> Result<JsValue, JsValue> {
+        let rule: opening::OpeningRule = rule.parse().map_err(|_| JsValue::from_str(&format!("unknown opening rule: {}", rule)))?;
+        let state: opening::OpeningState = self._start_opening(rule).map_err(|e| JsValue::from_str(&e))?;
+
+        Ok(serde_wasm_bindgen::to_value(&state)?)
+    }
+
+    pub fn opening_state(&mut self) -> Result<JsValue, JsValue> {
+        let state: opening::OpeningState = self.opening_step(|_, _| Ok(())).map_err(|e| JsValue::from_str(&e))?;
+
+        Ok(serde_wasm_bindgen::to_value(&state)?)
+    }
+
+    pub fn opening_play(&mut self, m: Move) -> Result<JsValue, JsValue> {
+        let state: opening::OpeningState = self.opening_step(|op, bd| op.play(bd, m)).map_err(|e| JsValue::from_str(&e))?;
+
+        Ok(serde_wasm_bindgen::to_value(&state)?)
+    }
+
+    pub fn opening_declare_fifths(&mut self, n: usize) -> Result<JsValue, JsValue> {
+        let state: opening::OpeningState = self.opening_step(|op, _| op.declare_fifths(n)).map_err(|e| JsValue::from_str(&e))?;
+
+        Ok(serde_wasm_bindgen::to_value(&state)?)
     }
-}
 
-unsafe fn reset_search() {
-    PLY_MAX  = 0;
-    ROOT_BESTS.clear();
-    reset_alpha_beta();
+    pub fn opening_swap(&mut self, swap: bool) -> Result<JsValue, JsValue> {
+        let state: opening::OpeningState = self.opening_step(|op, _| op.swap(swap)).map_err(|e| JsValue::from_str(&e))?;
+
+        Ok(serde_wasm_bindgen::to_value(&state)?)
+    }
+
+    pub fn opening_propose_fifths(&mut self, ms: JsValue) -> Result<JsValue, JsValue> {
+        let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
+        let state: opening::OpeningState = self.opening_step(|op, bd| op.propose_fifths(bd, &moves)).map_err(|e| JsValue::from_str(&e))?;
+
+        Ok(serde_wasm_bindgen::to_value(&state)?)
+    }
+
+    pub fn opening_choose_fifth(&mut self, m: Move) -> Result<JsValue, JsValue> {
+        let state: opening::OpeningState = self.opening_step(|op, bd| op.choose_fifth(bd, m)).map_err(|e| JsValue::from_str(&e))?;
+
+        Ok(serde_wasm_bindgen::to_value(&state)?)
+    }
 }
 
-pub unsafe fn search() -> Move {
-    let mut rem: RootExtMove = RootExtMove::new();
-    let mut valid_result: bool = true;
-    let mut break_search: bool = false;
+impl Engine {
+    pub fn _think_and_move(&mut self, moves: &[Move], tl: i32) -> SearchResult {
+        self.replay(moves);
+        self.clear_stop();
 
-    for it_depth in DEPTH_ITERATIVE_MIN..DEPTH_ITERATIVE_MAX+1 {
-        itd = it_depth as i32;
-        reset_alpha_beta();
-        let score = alpha_beta(NodeType::PV, it_depth, -INFINITY_SCORE, INFINITY_SCORE, false);
-        rem.set(score, it_depth, SEARCH_STACK[0].pv.clone());
+        self.think(tl.saturating_mul(1000))
+    }
 
-        if terminated() && is_empty(SEARCH_STACK[0].pv.as_ref()) {
-            valid_result = false;
-        }
+    pub fn _analyze(&mut self, moves: &[Move], tl: i32, n: usize) -> Vec<SearchResult> {
+        self.replay(moves);
+        self.clear_stop();
+
+        self.think_multi_pv(tl.saturating_mul(1000), n)
+    }
+
+    // Like `think`, but stops as soon as the time manager is happy with the best move
+    pub fn think_with_clock(&mut self, tc: &TimeControl) -> SearchResult {
+        let tm: TimeManager = TimeManager::new(tc, self.board.piece_cnt);
+        let hard: i32 = tm.hard_limit();
 
-        if terminated() || (rem.score.abs() > WIN_SCORE_THRESHOLD && it_depth as Score >= WIN_SCORE - rem.score.abs()) {
-            break_search = true;
+        self.clear_stop();
+        self.time_manager = Some(tm);
+        let result: SearchResult = self.think(hard);
+        self.time_manager = None;
+
+        result
+    }
+
+    // Searches without a deadline up to `depth` iterations and `nodes` nodes, 0 meaning no bound.
+    // Hash and move-ordering history are cleared first so that the result only depends on the position.
+    pub fn think_limited(&mut self, depth: Depth, nodes: u64) -> SearchResult {
+        self.clear_hash();
+        self.clear_history();
+        self.depth_limit = if depth > 0 {cmp::min(depth, DEPTH_ITERATIVE_MAX)} else {DEPTH_ITERATIVE_MAX};
+        self.node_limit = nodes;
+        self.clear_stop();
+
+        let result: SearchResult = self.think(TIME_INFINITE);
+
+        self.depth_limit = DEPTH_ITERATIVE_MAX;
+        self.node_limit = 0;
+
+        result
+    }
+
+    fn clear_history(&mut self) {
+        for ss in self.search_stack.iter_mut() {
+            ss.killers = [MOVE_NONE; 2];
+        }
+        for cm in self.counter_moves.iter_mut() {
+            *cm = MOVE_NONE;
         }
+    }
 
-        if valid_result {
-            ROOT_BESTS.push(rem);
+    // Swap1 and Swap2: with no stones place a balanced three-stone opening, after three stones pick a colour
+    // or (Swap2) place two more balanced stones, after five (Swap2) pick a colour. None for any other count.
+    pub fn _swap_move(&mut self, moves: &[Move], swap2: bool) -> Option<SwapResult> {
+        if !moves.is_empty() && moves.len() != 3 && !(swap2 && moves.len() == 5) {
+            return None;
         }
 
-        if break_search {
-            break;
+        self.reset();
+        for m in moves.iter() {
+            self.board.do_move(*m);
         }
+        self.clear_hash();
+        self.clear_history();
+        // A stop stays raised for the rest of the swap decision, across its several searches
+        self.clear_stop();
+
+        let progress = self.progress.take();
+        let result: SwapResult = match moves.len() {
+            0 => self.balanced_opening(),
+            _ => {
+                let best: SearchResult = self.judge_lines(1).swap_remove(0);
+
+                if best.score > BALANCE_MARGIN || (best.score >= 0 && (moves.len() == 5 || !swap2)) {
+                    SwapResult::new(SwapChoice::White, &[best.best_move], best.score)
+                } else if best.score < -BALANCE_MARGIN || moves.len() == 5 || !swap2 {
+                    SwapResult::new(SwapChoice::Black, &[], -best.score)
+                } else {
+                    self.balanced_pair()
+                }
+            },
+        };
+        self.progress = progress;
+
+        Some(result)
     }
-    ROOT_BESTS.last().unwrap_unchecked().pv[0]
-}
 
-#[wasm_bindgen]
-pub fn make_move(r: i32, f: i32) -> Move {
-    _make_move(&r, &f)
-}
+    // Short fixed search of the current position, best line first
+    fn judge_lines(&mut self, n: usize) -> Vec<SearchResult> {
+        self.depth_limit = JUDGE_DEPTH;
+        self.node_limit = JUDGE_NODES;
 
-#[wasm_bindgen]
-pub fn rank_of(m: Move) -> i32 {
-    _rank_of(m)
-}
+        let lines: Vec<SearchResult> = self.think_multi_pv(TIME_INFINITE, n);
 
-#[wasm_bindgen]
-pub fn file_of(m: Move) -> i32 {
-    _file_of(m)
-}
+        self.depth_limit = DEPTH_ITERATIVE_MAX;
+        self.node_limit = 0;
 
-#[wasm_bindgen]
-pub unsafe fn foul_moves(ms: JsValue) -> Result<JsValue, JsValue> {
-    let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
-    let mut foul_moves: Vec<Move> = Vec::new();
-
-    reset_search();
-    BOARD.as_mut().unwrap().reset();
-    
-    for m in moves.iter() {
-        BOARD.as_mut().unwrap().do_move(*m);
+        lines
     }
 
-    for m in 0..MOVE_CAPACITY as Move {
-        if BOARD.as_ref().unwrap().is_empty(m) {
-            if BOARD.as_mut().unwrap().is_foul(m) {
-                foul_moves.push(m);
+    // Black in the centre, White next to it, and the third stone within two squares that leaves White closest to even
+    fn balanced_opening(&mut self) -> SwapResult {
+        let c: i32 = self.board.side() / 2;
+        let center: Move = _make_move(&c, &c);
+        let mut best: (Score, [Move; 3]) = (SCORE_NONE, [MOVE_NONE; 3]);
+
+        self.board.do_move(center);
+        // Every other adjacent square is a reflection of one of these two
+        for w in [_make_move(&(c - 1), &c), _make_move(&(c - 1), &(c + 1))] {
+            self.board.do_move(w);
+            for r in c - 2..c + 3 {
+                for f in c - 2..c + 3 {
+                    let b: Move = _make_move(&r, &f);
+                    if !self.board.is_empty(b) {
+                        continue;
+                    }
+
+                    self.board.do_move(b);
+                    let score: Score = self.judge_lines(1)[0].score;
+                    self.board.undo_move();
+
+                    if best.0 == SCORE_NONE || score.abs() < best.0.abs() {
+                        best = (score, [center, w, b]);
+                    }
+                }
             }
+            self.board.undo_move();
         }
+        self.board.undo_move();
+
+        SwapResult::new(SwapChoice::Place, &best.1, best.0)
     }
 
-    return Ok(serde_wasm_bindgen::to_value(&foul_moves)?);
-}
+    // Fourth and fifth stones among the strongest moves of each side that leave White closest to even
+    fn balanced_pair(&mut self) -> SwapResult {
+        let mut best: (Score, [Move; 2]) = (SCORE_NONE, [MOVE_NONE; 2]);
 
-#[wasm_bindgen]
-pub unsafe fn check_wld_already(ms: JsValue) -> Result<i32, JsValue> {
-    let moves: Vec<Move> = serde_wasm_bindgen::from_value(ms)?;
-    let mut foul_moves: Vec<Move> = Vec::new();
-
-    reset_search();
-    BOARD.as_mut().unwrap().reset();
-    
-    for m in moves.iter() {
-        BOARD.as_mut().unwrap().do_move(*m);
-    }
-
-    return Ok(match BOARD.as_ref().unwrap().check_wld_already() {
-        Color::Black => 1,
-        Color::White => 2,
-        Color::Hide => 3,
-        Color::None => 0
-    });
-}
-
-unsafe fn terminated() -> bool {
-    let elasped = performance.now() - PERF_START;
-    let limit = (TIME_LIMIT * 1000 - 50) as f64;
+        for w in self.judge_lines(PAIR_CANDIDATES).iter().map(|r| r.best_move).filter(|m| *m != MOVE_NONE) {
+            self.board.do_move(w);
+            for b in self.judge_lines(PAIR_CANDIDATES).iter().map(|r| r.best_move).filter(|m| *m != MOVE_NONE) {
+                self.board.do_move(b);
+                let score: Score = self.judge_lines(1)[0].score;
+                self.board.undo_move();
 
-    return elasped >= limit;
-}
+                if best.0 == SCORE_NONE || score.abs() < best.0.abs() {
+                    best = (score, [w, b]);
+                }
+            }
+            self.board.undo_move();
+        }
 
-unsafe fn alpha_beta(nt: NodeType, depth: Depth, mut alpha: Score, mut beta: Score, cautious: bool) -> Score {
-    if terminated() {
-        return if (PLY as u32 & (1 as u32)) != 0 {WIN_SCORE} else {-WIN_SCORE};
+        SwapResult::new(SwapChoice::Place, &best.1, best.0)
     }
 
-    if PLY_MAX < PLY {
-        PLY_MAX = PLY;
+    // Like `think`, but also reports the runner-up root moves, each searched to the same depth
+    pub fn think_multi_pv(&mut self, time_limit: i32, n: usize) -> Vec<SearchResult> {
+        self.multi_pv = cmp::max(n, 1);
+        let best: SearchResult = self.think(time_limit);
+        self.multi_pv = 1;
+
+        self.root_lines.iter().map(|rem| SearchResult::new(rem, best.seldepth, best.nodes, best.elapsed)).collect()
     }
 
-    let pv_node: bool = nt == NodeType::PV;
-    let root_node: bool = PLY == 0;
-    let mut offset: Score = -1;
-    let wld: Color = BOARD.as_ref().unwrap().check_wld(&mut offset);
-    if offset != -1 {
-        if wld == BOARD.as_ref().unwrap().side_to_move {
-            return WIN_SCORE - PLY as Score - offset;
-        } else if wld == BOARD.as_ref().unwrap().oppo_to_move {
-            return -WIN_SCORE + PLY as Score + offset;
-        } else if wld == Color::Hide {
-            return 0;
+    // Searches the current position for at most `time_limit` milliseconds, or until stopped.
+    // A raised stop flag stays raised; the search commands lower it before they start.
+    pub fn think(&mut self, time_limit: i32) -> SearchResult {
+        self.perf_start = clock::now();
+        self.time_limit = time_limit;
+
+        unsafe {
+            self.reset_search();
         }
-    }
 
-    if !root_node {
-        alpha = cmp::max(-WIN_SCORE + PLY as Score, alpha);
-        beta = cmp::min(WIN_SCORE - PLY as Score - 1, beta);
-        if alpha >= beta {
-            return alpha;
+        let mut skip_search: bool = false;
+        let mut rem: RootExtMove = RootExtMove::new();
+        let mut fallback: Move = MOVE_NONE;
+        {
+            let mut mg: MoveGen = MoveGen::new(&mut self.board, ());
+            let mut em: ExtMove;
+            let mut offset: Score = -1;
+
+            self.tt.new_search();
+
+            if !skip_search && self.board.piece_cnt == 0 {
+                rem.score = 0;
+                rem.depth = 1 as Depth;
+                unsafe { update_pv2(Some(&mut rem.pv), make_move(self.board.side() / 2, self.board.side() / 2)); }
+                skip_search = true;
+            }
+
+            if !skip_search && self.board.check_wld(&mut offset) != Color::None {
+                em = mg.generate(&mut self.board, GenType::WLD);
+                rem.score = em.s;
+                rem.depth = offset as Depth;
+                unsafe { update_pv2(Some(&mut rem.pv), em.m); }
+                skip_search = true;
+            }
+
+            if !skip_search {
+                em = mg.generate(&mut self.board, GenType::MAIN);
+                fallback = em.m;
+                if mg.size() == 1 {
+                    rem.score = 0;
+                    rem.depth = 1 as Depth;
+                    unsafe { update_pv2(Some(&mut rem.pv), em.m); }
+                    skip_search = true;
+                }
+            }
         }
-    }
 
-    let mut static_score: Score = BOARD.as_ref().unwrap().evaluate();
-    let mut score: Score = static_score;
+        unsafe {
+            self.root_lines.clear();
 
-    if depth <= 0 || PLY >= DEPTH_MAX {
-        if static_score < beta && BOARD.as_ref().unwrap().query(BOARD.as_ref().unwrap().side_to_move, types::Material::B3) > 0 {
-            score = vcf(nt, cmp::min(PLY * 2, DEPTH_MAX), true);
+            if skip_search {
+                self.root_bests.push(rem);
+            } else {
+                self.search();
+            }
 
-            if score > WIN_SCORE_THRESHOLD {
-                return score;
+            // Out of time before the first iteration completed
+            if self.root_bests.is_empty() {
+                rem.score = 0;
+                rem.depth = 0;
+                update_pv2(Some(&mut rem.pv), fallback);
+                self.root_bests.push(rem);
             }
+
+            // The best line may come from a later depth than the runner-ups
+            let best: RootExtMove = *self.root_bests.last().unwrap_unchecked();
+            self.root_lines.retain(|l| l.pv[0] != best.pv[0]);
+            self.root_lines.insert(0, best);
+            self.root_lines.truncate(self.multi_pv);
+
+            SearchResult::new(self.root_bests.last().unwrap_unchecked(), self.ply_max, self.nodes, clock::now() - self.perf_start)
         }
-        return static_score;
     }
 
-    let mut best_move: Move = MOVE_NONE;
-    let mut tt_move: Move = MOVE_NONE;
-    let mut best_score: Score = -INFINITY_SCORE;
-    let mut tt_score: Score = SCORE_NONE;
-    let key: ZobristKey = BOARD.as_ref().unwrap().key;
-    let mut tt_entry: &mut TTEntry = &mut Default::default();
-    let mut tt_hit: bool = false;
-    let mut move_count: i32 = 0;
-    let defend_b4: bool = BOARD.as_ref().unwrap().query(BOARD.as_ref().unwrap().oppo_to_move, types::Material::B4) > 0;
-    let quiet_node: bool = BOARD.as_ref().unwrap().is_quiet(None);
-    let mut extend: bool = false;
-    let mut do_full_depth_search: bool = false;
-    let mut child_pv: Pv = [MOVE_NONE; STACK_SIZE as usize]; 
-    let mut new_depth: Depth;
-    reset_pv(&mut child_pv);
+    pub fn reset(&mut self) {
+        self.board.reset();
+    }
 
-    SEARCH_STACK[PLY as usize + 2].killers[0] = MOVE_NONE;
-    SEARCH_STACK[PLY as usize + 2].killers[1] = MOVE_NONE;
+    // Lets another thread stop a running search
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
 
-    // tt_entry = TT.probe(&key, &mut tt_hit);
+    pub fn set_progress(&mut self, f: Option<ProgressCallback>) {
+        self.progress = f;
+    }
 
-    if root_node && ROOT_BESTS.len() != 0 {
-        tt_move = ROOT_BESTS.last().unwrap_unchecked().pv[0];
-        tt_score = ROOT_BESTS.last().unwrap_unchecked().score;
-    } else if tt_hit {
-        tt_move = tt_entry.move16();
-        tt_score = score_from_tt(tt_entry.score());
-    } else {
-        tt_move = MOVE_NONE;
-        score = SCORE_NONE;
-    };
+    pub fn do_move(&mut self, m: Move) {
+        self.board.do_move(m);
+    }
+
+    pub fn undo_move(&mut self) {
+        self.board.undo_move();
+    }
+
+    // Sets the position up; games replayed from its move list never judge its stones as fouls
+    pub fn setup(&mut self, black: &[Move], white: &[Move], side_to_move: Color) -> bool {
+        if !self.board.setup(black, white, side_to_move) {
+            return false;
+        }
+        self.setup = _setup_moves(black, white, side_to_move);
+
+        true
+    }
+
+    // Plays `moves` from an empty board, the stones of the last set-up position as set up if they lead the list
+    fn replay(&mut self, moves: &[Move]) {
+        let n: usize = if !self.setup.is_empty() && moves.starts_with(&self.setup) {self.setup.len()} else {0};
 
-    if !pv_node && tt_hit && tt_score > WIN_SCORE_THRESHOLD && (tt_entry.bound() as u8 & Bound::BoundLower as u8) != 0 {
-        // Update history
-        if tt_score >= beta {
-            update_history(tt_move);
+        self.board.reset();
+        for m in moves[..n].iter() {
+            self.board.do_move(*m);
+        }
+        self.board.end_setup();
+        for m in moves[n..].iter() {
+            self.board.do_move(*m);
         }
-        return tt_score;
     }
 
-    if !pv_node && tt_hit && tt_entry.depth() >= depth && tt_score != SCORE_NONE && if tt_score >= beta { (tt_entry.bound() as u8 & Bound::BoundLower as u8) != 0 } else { (tt_entry.bound() as u8 & Bound::BoundUpper as u8) != 0 }  {
-        if tt_score >= beta {
-            update_history(tt_move);
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn _load_psq(&mut self, s: &str) -> Result<psq::PsqGame, String> {
+        let game: psq::PsqGame = psq::read_psq(s, &mut self.board)?;
+        self.tt.clear();
+
+        Ok(game)
+    }
+
+    pub fn _foul_moves(&mut self, moves: &[Move]) -> Vec<Move> {
+        let mut foul_moves: Vec<Move> = Vec::new();
+
+        unsafe {
+            self.reset_search();
+        }
+        self.replay(moves);
+
+        for m in 0..MOVE_CAPACITY as Move {
+            if self.board.is_empty(m) && self.board.is_foul(m) {
+                foul_moves.push(m);
+            }
         }
-        return tt_score;
+
+        foul_moves
     }
 
-    'outer: {
-        if defend_b4 {
-            break 'outer;
+    pub fn _fouls(&mut self, moves: &[Move]) -> Vec<Foul> {
+        let mut fouls: Vec<Foul> = Vec::new();
+
+        unsafe {
+            self.reset_search();
         }
+        self.replay(moves);
 
-        if tt_hit && (tt_entry.bound() as u8 & (if tt_score > static_score {Bound::BoundLower} else {Bound::BoundUpper}) as u8) != 0 {
-            static_score = tt_score;
+        for m in 0..MOVE_CAPACITY as Move {
+            if self.board.is_empty(m) {
+                if let Some(foul) = self.board.foul_of(m) {
+                    fouls.push(foul);
+                }
+            }
         }
 
-        if !root_node && depth < 5 && static_score + futility_margin(depth) <= alpha {
-            return alpha_beta(nt, 0, alpha, beta, cautious);
+        fouls
+    }
+
+    pub fn _check_wld_already(&mut self, moves: &[Move]) -> i32 {
+        unsafe {
+            self.reset_search();
         }
+        self.replay(moves);
 
-        if !root_node && depth < 7 && static_score - futility_margin(depth) >= beta && static_score < WIN_SCORE_THRESHOLD { // Do not return not verified wins
-            return static_score;
+        match self.board.check_wld_already() {
+            Color::Black => 1,
+            Color::White => 2,
+            Color::Hide => 3,
+            Color::None => 0
         }
+    }
+
+    // Takes the rule and size of a one-line position and returns the moves reaching it
+    pub fn set_position(&mut self, s: &str) -> Result<Vec<Move>, String> {
+        let pos: position::Position = position::Position::parse(s)?;
+        pos.to_board(&mut self.board)?;
+        self.tt.clear();
+        self.setup = pos.moves();
+
+        Ok(pos.moves())
+    }
+
+    pub fn _position_string(&mut self, moves: &[Move]) -> String {
+        self.replay(moves);
+
+        let mut pos: position::Position = position::Position::from_board(&self.board);
+        pos.move_number = Some(moves.len() as u32 + 1);
 
-        if depth >= 7 && tt_move == MOVE_NONE {
-            alpha_beta(nt, depth / 2, alpha, beta, cautious);
-            // tt_entry = TT.probe(&key, &mut tt_hit);
-            tt_move  = if tt_hit { tt_entry.move16() } else { MOVE_NONE };
-            tt_score = if tt_hit { score_from_tt(tt_entry.score()) } else { SCORE_NONE };
+        pos.to_string()
+    }
+
+    pub fn _game_end(&mut self, moves: &[Move]) -> Option<GameEnd> {
+        unsafe {
+            self.reset_search();
         }
+        self.replay(moves);
+
+        self.board.game_end()
+    }
+
+    pub fn _start_opening(&mut self, rule: opening::OpeningRule) -> Result<opening::OpeningState, String> {
+        let op: opening::Opening = opening::Opening::new(rule, &mut self.board)?;
+        let state: opening::OpeningState = op.state();
+        self.opening = Some(op);
+
+        Ok(state)
     }
 
-    let cm: Move = if BOARD.as_ref().unwrap().piece_cnt >= 1 { COUNTER_MOVES[BOARD.as_ref().unwrap().last_move(1) as usize] } else { MOVE_NONE };
-    let karr1: Move = SEARCH_STACK[PLY as usize].killers[0];
-    let karr2: Move = SEARCH_STACK[PLY as usize].killers[1];
+    // Applies one decision to the running opening. Other calls may have used the board in between, so the
+    // opening stones are put back first.
+    pub fn opening_step<F>(&mut self, f: F) -> Result<opening::OpeningState, String>
+    where
+        F: FnOnce(&mut opening::Opening, &mut Board) -> Result<(), String>,
+    {
+        let op: &mut opening::Opening = self.opening.as_mut().ok_or("no opening has been started")?;
+
+        self.board.reset();
+        for m in op.moves().iter() {
+            self.board.do_move(*m);
+        }
 
-    let mut mg: MoveGen = MoveGen::new((Stage::MainTT, tt_move, false, PLY, karr1, karr2, cm));
-    let mut em: ExtMove;
+        f(op, &mut self.board)?;
 
-    loop {
-        em = mg.next_move();
-        if em.m == MOVE_NONE {
-            break;
+        Ok(op.state())
+    }
+
+    unsafe fn reset_alpha_beta(&mut self) {
+        self.ply = 0;
+        if self.search_stack[0].pv.is_some() {
+            reset_pv(self.search_stack[0].pv.as_mut().unwrap_unchecked());
+        } else {
+            self.search_stack[0].pv = Some([0; STACK_SIZE as usize]);
         }
+    }
 
-        move_count += 1;
-        if !cautious && PLY >= 2 {
-            if move_count > FUTILITY_MOVE_COUNT[if quiet_node {1} else {0}][depth as usize] {
-                break;
+    unsafe fn reset_search(&mut self) {
+        self.ply_max  = 0;
+        self.nodes = 0;
+        self.root_bests.clear();
+        self.reset_alpha_beta();
+    }
+
+    unsafe fn search(&mut self) -> Move {
+        let mut rem: RootExtMove = RootExtMove::new();
+        let mut valid_result: bool = true;
+        let mut break_search: bool = false;
+
+        for it_depth in DEPTH_ITERATIVE_MIN..self.depth_limit+1 {
+            self.reset_alpha_beta();
+            let score = self.alpha_beta(NodeType::PV, it_depth, -INFINITY_SCORE, INFINITY_SCORE, false);
+            rem.set(score, it_depth, self.search_stack[0].pv);
+
+            if self.terminated() && is_empty(self.search_stack[0].pv.as_ref()) {
+                valid_result = false;
             }
-        } else {
-            if move_count > FUTILITY_MOVE_COUNT[if quiet_node {1} else {0}][depth as usize] && em.s < SEE_THRESHOLD {
+
+            if self.terminated() || (rem.score.abs() > WIN_SCORE_THRESHOLD && it_depth as Score >= WIN_SCORE - rem.score.abs()) {
+                break_search = true;
+            }
+
+            if valid_result {
+                self.root_bests.push(rem);
+
+                if let Some(progress) = self.progress.as_mut() {
+                    progress(&SearchResult::new(&rem, self.ply_max, self.nodes, clock::now() - self.perf_start));
+                }
+            }
+
+            if valid_result && !break_search {
+                let elapsed: f64 = clock::now() - self.perf_start;
+                if let Some(tm) = self.time_manager.as_mut() {
+                    break_search = tm.stop_early(&rem, elapsed);
+                }
+            }
+
+            if valid_result && !break_search && self.multi_pv > 1 {
+                self.search_lines(it_depth, rem);
+            }
+
+            if break_search {
                 break;
             }
         }
+        self.root_bests.last().map_or(MOVE_NONE, |rem| rem.pv[0])
+    }
 
-        // prepetch
+    // Searches the root again with the better moves excluded to find the next `multi_pv - 1` lines
+    unsafe fn search_lines(&mut self, depth: Depth, best: RootExtMove) {
+        let mut lines: Vec<RootExtMove> = vec![best];
+        let mut rem: RootExtMove = RootExtMove::new();
 
-        new_depth = depth - 1;
+        self.root_excluded.clear();
+        self.root_excluded.push(best.pv[0]);
 
-        if defend_b4 {
-            extend = true;
+        while lines.len() < self.multi_pv {
+            self.reset_alpha_beta();
+            let score = self.alpha_beta(NodeType::PV, depth, -INFINITY_SCORE, INFINITY_SCORE, false);
+
+            if self.terminated() || is_empty(self.search_stack[0].pv.as_ref()) {
+                break;
+            }
+
+            rem.set(score, depth, self.search_stack[0].pv);
+            self.root_excluded.push(rem.pv[0]);
+            lines.push(rem);
         }
 
-        if extend {
-            new_depth = cmp::min(new_depth + 1, DEPTH_MAX);
-        } else {
-            new_depth = cmp::min(new_depth, DEPTH_MAX);
+        // Keep the lines of the previous depth that this one did not reach
+        if self.terminated() {
+            for old in self.root_lines.iter() {
+                if lines.len() < self.multi_pv && !lines.iter().any(|l| l.pv[0] == old.pv[0]) {
+                    lines.push(*old);
+                }
+            }
         }
 
-        PLY += 1;
-        SEARCH_STACK[PLY as usize].pv = Some(child_pv);
-        BOARD.as_mut().unwrap().do_move(em.m);
+        self.root_excluded.clear();
+        self.root_lines = lines;
+    }
 
-        if depth >= 3 && move_count > 1 {
-            let r: Depth = REDUCTION[if pv_node {1} else {0}][depth as usize][move_count as usize];
-            let d: Depth = if new_depth - r >= 1 && r >= 0 {new_depth - r} else if new_depth - r < 1 {1} else {new_depth};
-            score = -alpha_beta(NodeType::NonPV, d, -alpha - 1, -alpha, cautious);
-            do_full_depth_search = score > alpha && d != new_depth;
-        } else {
-            do_full_depth_search = !pv_node || move_count > 1;
+    fn terminated(&self) -> bool {
+        if self.stop.load(Ordering::Relaxed) {
+            return true;
         }
 
-        if do_full_depth_search {
-            score = -alpha_beta(NodeType::NonPV, new_depth, -alpha - 1, -alpha, cautious);
+        if self.node_limit > 0 && self.nodes >= self.node_limit {
+            return true;
         }
 
-        if pv_node && (move_count == 1 || (score > alpha && (root_node || score < beta))) {
-            score = -alpha_beta(NodeType::PV, new_depth, -beta, -alpha, cautious);
+        let elasped = clock::now() - self.perf_start;
+        let limit = (self.time_limit - MOVE_OVERHEAD) as f64;
+
+        elasped >= limit
+    }
+
+    unsafe fn alpha_beta(&mut self, nt: NodeType, depth: Depth, mut alpha: Score, mut beta: Score, cautious: bool) -> Score {
+        if self.terminated() {
+            return if (self.ply as u32 & 1u32) != 0 {WIN_SCORE} else {-WIN_SCORE};
         }
 
-        if pv_node && PLY >= 2 && !cautious && score > WIN_SCORE_THRESHOLD {
-            let s: Score = -alpha_beta(NodeType::PV, new_depth, -WIN_SCORE_THRESHOLD, -WIN_SCORE_THRESHOLD + 1, true);
+        self.nodes += 1;
+
+        if self.ply_max < self.ply {
+            self.ply_max = self.ply;
+        }
 
-            if s < WIN_SCORE_THRESHOLD {
-                score = -alpha_beta(NodeType::PV, new_depth, -beta, -alpha, true);
+        let pv_node: bool = nt == NodeType::PV;
+        let root_node: bool = self.ply == 0;
+        let mut offset: Score = -1;
+        let wld: Color = self.board.check_wld(&mut offset);
+        if offset != -1 {
+            if wld == self.board.side_to_move {
+                return WIN_SCORE - self.ply as Score - offset;
+            } else if wld == self.board.oppo_to_move {
+                return -WIN_SCORE + self.ply as Score + offset;
+            } else if wld == Color::Hide {
+                return 0;
             }
         }
 
-        BOARD.as_mut().unwrap().undo_move();
-        PLY -= 1;
-        if terminated()  {
-            return best_score;
+        if !root_node {
+            alpha = cmp::max(-WIN_SCORE + self.ply as Score, alpha);
+            beta = cmp::min(WIN_SCORE - self.ply as Score - 1, beta);
+            if alpha >= beta {
+                return alpha;
+            }
         }
 
-        if score > best_score {
-            best_score = score;
+        let mut static_score: Score = self.board.evaluate();
+        let mut score: Score = static_score;
 
-            if score > alpha {
-                best_move = em.m;
+        if depth <= 0 || self.ply >= DEPTH_MAX {
+            if static_score < beta && self.board.query(self.board.side_to_move, types::Material::B3) > 0 {
+                score = self.vcf(nt, cmp::min(self.ply * 2, DEPTH_MAX), true);
 
-                if pv_node && (!root_node || score < beta) {
-                    let cpv = SEARCH_STACK[PLY as usize + 1].pv;
-                    update_pv3(SEARCH_STACK[PLY as usize].pv.as_mut(), em.m, cpv);
+                if score > WIN_SCORE_THRESHOLD {
+                    return score;
                 }
+            }
+            return static_score;
+        }
 
-                if pv_node && score < beta {
-                    alpha = score;
-                } else {
+        let mut best_move: Move = MOVE_NONE;
+        let mut tt_move: Move;
+        let mut best_score: Score = -INFINITY_SCORE;
+        let mut tt_score: Score = SCORE_NONE;
+        let key: ZobristKey = self.board.key;
+        let mut tt_hit: bool = false;
+        let mut tt_index: usize = self.tt.probe(&key, &mut tt_hit);
+        let mut tt_entry: TTEntry = self.tt.entry(tt_index);
+        let mut move_count: i32 = 0;
+        let defend_b4: bool = self.board.query(self.board.oppo_to_move, types::Material::B4) > 0;
+        let quiet_node: bool = self.board.is_quiet(None);
+        let mut extend: bool = false;
+        let mut do_full_depth_search: bool;
+        let mut child_pv: Pv = [MOVE_NONE; STACK_SIZE as usize]; 
+        let mut new_depth: Depth;
+        reset_pv(&mut child_pv);
+
+        self.search_stack[self.ply as usize + 2].killers[0] = MOVE_NONE;
+        self.search_stack[self.ply as usize + 2].killers[1] = MOVE_NONE;
+
+        if root_node && !self.root_bests.is_empty() {
+            tt_move = self.root_bests.last().unwrap_unchecked().pv[0];
+            tt_score = self.root_bests.last().unwrap_unchecked().score;
+        } else if tt_hit {
+            tt_move = tt_entry.move16();
+            tt_score = self.score_from_tt(tt_entry.score());
+        } else {
+            tt_move = MOVE_NONE;
+            score = SCORE_NONE;
+        };
+
+        if !pv_node && tt_hit && tt_score > WIN_SCORE_THRESHOLD && (tt_entry.bound() as u8 & Bound::BoundLower as u8) != 0 {
+            // Update history
+            if tt_score >= beta {
+                self.update_history(tt_move);
+            }
+            return tt_score;
+        }
+
+        if !pv_node && tt_hit && tt_entry.depth() >= depth && tt_score != SCORE_NONE && if tt_score >= beta { (tt_entry.bound() as u8 & Bound::BoundLower as u8) != 0 } else { (tt_entry.bound() as u8 & Bound::BoundUpper as u8) != 0 }  {
+            if tt_score >= beta {
+                self.update_history(tt_move);
+            }
+            return tt_score;
+        }
+
+        'outer: {
+            if defend_b4 {
+                break 'outer;
+            }
+
+            if tt_hit && (tt_entry.bound() as u8 & (if tt_score > static_score {Bound::BoundLower} else {Bound::BoundUpper}) as u8) != 0 {
+                static_score = tt_score;
+            }
+
+            if !root_node && depth < 5 && static_score + futility_margin(depth) <= alpha {
+                return self.alpha_beta(nt, 0, alpha, beta, cautious);
+            }
+
+            if !root_node && depth < 7 && static_score - futility_margin(depth) >= beta && static_score < WIN_SCORE_THRESHOLD { // Do not return not verified wins
+                return static_score;
+            }
+
+            if depth >= 7 && tt_move == MOVE_NONE {
+                self.alpha_beta(nt, depth / 2, alpha, beta, cautious);
+                tt_index = self.tt.probe(&key, &mut tt_hit);
+                tt_entry = self.tt.entry(tt_index);
+                tt_move  = if tt_hit { tt_entry.move16() } else { MOVE_NONE };
+            }
+        }
+
+        let cm: Move = if self.board.piece_cnt >= 1 { self.counter_moves[self.board.last_move(1) as usize] } else { MOVE_NONE };
+        let karr1: Move = self.search_stack[self.ply as usize].killers[0];
+        let karr2: Move = self.search_stack[self.ply as usize].killers[1];
+
+        let mut mg: MoveGen = MoveGen::new(&mut self.board, (Stage::MainTT, tt_move, false, self.ply, karr1, karr2, cm));
+        let mut em: ExtMove;
+
+        loop {
+            em = mg.next_move(&mut self.board);
+            if em.m == MOVE_NONE {
+                break;
+            }
+
+            if root_node && self.root_excluded.contains(&em.m) {
+                continue;
+            }
+
+            // Deeper nodes only screen fouls by patterns, the root plays none
+            if root_node && self.board.side_to_move == Color::Black && self.board.is_foul(em.m) {
+                continue;
+            }
+
+            move_count += 1;
+            if !cautious && self.ply >= 2 {
+                if move_count > self.futility_move_count[if quiet_node {1} else {0}][depth as usize] {
                     break;
                 }
+            } else {
+                if move_count > self.futility_move_count[if quiet_node {1} else {0}][depth as usize] && em.s < SEE_THRESHOLD {
+                    break;
+                }
+            }
+
+            // prepetch
+
+            new_depth = depth - 1;
+
+            if defend_b4 {
+                extend = true;
+            }
+
+            if extend {
+                new_depth = cmp::min(new_depth + 1, DEPTH_MAX);
+            } else {
+                new_depth = cmp::min(new_depth, DEPTH_MAX);
+            }
+
+            self.ply += 1;
+            self.search_stack[self.ply as usize].pv = Some(child_pv);
+            self.board.do_move(em.m);
+
+            if depth >= 3 && move_count > 1 {
+                let r: Depth = self.reduction[if pv_node {1} else {0}][depth as usize][move_count as usize];
+                let d: Depth = if new_depth - r >= 1 && r >= 0 {new_depth - r} else if new_depth - r < 1 {1} else {new_depth};
+                score = -self.alpha_beta(NodeType::NonPV, d, -alpha - 1, -alpha, cautious);
+                do_full_depth_search = score > alpha && d != new_depth;
+            } else {
+                do_full_depth_search = !pv_node || move_count > 1;
+            }
+
+            if do_full_depth_search {
+                score = -self.alpha_beta(NodeType::NonPV, new_depth, -alpha - 1, -alpha, cautious);
+            }
+
+            if pv_node && (move_count == 1 || (score > alpha && (root_node || score < beta))) {
+                score = -self.alpha_beta(NodeType::PV, new_depth, -beta, -alpha, cautious);
+            }
+
+            if pv_node && self.ply >= 2 && !cautious && score > WIN_SCORE_THRESHOLD {
+                let s: Score = -self.alpha_beta(NodeType::PV, new_depth, -WIN_SCORE_THRESHOLD, -WIN_SCORE_THRESHOLD + 1, true);
+
+                if s < WIN_SCORE_THRESHOLD {
+                    score = -self.alpha_beta(NodeType::PV, new_depth, -beta, -alpha, true);
+                }
+            }
+
+            self.board.undo_move();
+            self.ply -= 1;
+            if self.terminated()  {
+                return best_score;
+            }
+
+            if score > best_score {
+                best_score = score;
+
+                if score > alpha {
+                    best_move = em.m;
+
+                    if pv_node && (!root_node || score < beta) {
+                        let cpv = self.search_stack[self.ply as usize + 1].pv;
+                        update_pv3(self.search_stack[self.ply as usize].pv.as_mut(), em.m, cpv);
+                    }
+
+                    if pv_node && score < beta {
+                        alpha = score;
+                    } else {
+                        break;
+                    }
+                }
             }
         }
-    }
 
-    if best_move != MOVE_NONE {
-        update_history(best_move);
+        if best_move != MOVE_NONE {
+            self.update_history(best_move);
+        }
+
+        // Excluded root moves make the score unusable for the position itself
+        if is_ok_score(best_score) && (!root_node || self.root_excluded.is_empty()) {
+            let bound: Bound = if best_score >= beta {Bound::BoundLower} else if pv_node && best_move != MOVE_NONE {Bound::BoundExact} else {Bound::BoundUpper};
+            self.tt.save(tt_index, key, best_move, self.score_to_tt(best_score), bound, pv_node, depth);
+        }
+
+        best_score
     }
 
-    // let bound: Bound = if best_score >= beta {Bound::BoundLower} else if pv_node && best_move != MOVE_NONE {Bound::BoundExact} else {Bound::BoundUpper};
-    // tt_entry.save(key, best_move, score_to_tt(best_score), bound, false, depth);
+    fn update_history(&mut self, tt_move: Move) {
+        self.search_stack[self.ply as usize].update_killers(tt_move);
+        if self.board.piece_cnt >= 1 {
+            self.counter_moves[self.board.last_move(1) as usize] = tt_move;
+        }
+    }
 
-    best_score
-}
+    fn score_to_tt(&self, s: Score) -> Score {
+        if s > WIN_SCORE_THRESHOLD {s + self.ply as Score} else if s < -WIN_SCORE_THRESHOLD {s - self.ply as Score} else {s}
+    }
 
-unsafe fn update_history(tt_move: Move) {
-    SEARCH_STACK[PLY as usize].update_killers(tt_move);
-    if BOARD.as_ref().unwrap().piece_cnt >= 1 {
-        COUNTER_MOVES[BOARD.as_mut().unwrap().last_move(1) as usize] = tt_move;
+    fn score_from_tt(&self, s: Score) -> Score {
+        if s == SCORE_NONE {s} else if s > WIN_SCORE_THRESHOLD {s - self.ply as Score} else if s < -WIN_SCORE_THRESHOLD {s + self.ply as Score} else {s}
     }
-}
 
-unsafe fn futility_margin(d: Depth) -> Score {
-    45 as Score * d as Score 
-}
+    unsafe fn vcf(&mut self, nt: NodeType, depth: Depth, root_node: bool) -> Score {
+        let pv_node: bool = nt == NodeType::PV;
+        let mut offset: Score = -1;
 
-unsafe fn score_to_tt(s: Score) -> Score {
-    return if s > WIN_SCORE_THRESHOLD {s + PLY as Score} else if s < -WIN_SCORE_THRESHOLD {s - PLY as Score} else {s};
-}
+        if !root_node {
+            self.nodes += 1;
+            self.ply_max = cmp::max(self.ply_max, self.ply);
+
+            let color = self.board.check_wld(&mut offset);
+            if offset != -1 {
+                if color == self.board.side_to_move {
+                    return WIN_SCORE - self.ply as Score - offset;
+                } else if color == self.board.oppo_to_move {
+                    return -WIN_SCORE + self.ply as Score + offset;
+                } else if color == Color::Hide {
+                    return 0; // draw
+                }
+            }
+        }
 
-unsafe fn score_from_tt(s: Score) -> Score {
-    return if s > WIN_SCORE_THRESHOLD {s - PLY as Score} else if s < -WIN_SCORE_THRESHOLD {s + PLY as Score} else {s};
-}
+        if depth <= 0 || self.ply >= DEPTH_MAX {
+            return 0;
+        }
 
-unsafe fn vcf(nt: NodeType, depth: Depth, root_node: bool) -> Score {
-    let pv_node: bool = nt == NodeType::PV;
-    let mut offset: Score = -1;
+        let mut b4d: Move;
+        let mut score: Score;
+        let mut best_score: Score;
+        let mut child_pv: Pv = [MOVE_NONE; STACK_SIZE as usize];
+        let mut move_count: i32;
+        best_score = -INFINITY_SCORE;
+        move_count = 0;
+        reset_pv(&mut child_pv);
 
-    if !root_node {
-        PLY_MAX = cmp::max(PLY_MAX, PLY);
+        let mut mg: MoveGen = MoveGen::new(&mut self.board, (Stage::VcfTT, MOVE_NONE, root_node));
+        let mut em: ExtMove;
 
-        let color = BOARD.as_ref().unwrap().check_wld(&mut offset);
-        if offset != -1 {
-            if color == BOARD.as_ref().unwrap().side_to_move {
-                return WIN_SCORE - PLY as Score - offset;
-            } else if color == BOARD.as_ref().unwrap().oppo_to_move {
-                return -WIN_SCORE + PLY as Score + offset;
-            } else if color == Color::Hide {
-                return 0; // draw
-            }
-        }
-    }
-
-    if depth <= 0 || PLY >= DEPTH_MAX {
-        return 0;
-    }
-
-    let mut b4d: Move;
-    let mut score: Score;
-    let mut best_score: Score;
-    let mut child_pv: Pv = [MOVE_NONE; STACK_SIZE as usize];
-    let mut move_count: i32;
-    best_score = -INFINITY_SCORE;
-    move_count = 0;
-    reset_pv(&mut child_pv);
-
-    let mut mg: MoveGen = MoveGen::new((Stage::VcfTT, MOVE_NONE, root_node));
-    let mut em: ExtMove;
-
-    loop {
-        em = mg.next_move();
-        if em.m == MOVE_NONE {
-            break;
-        }
-        move_count += 1;
-        if !root_node && move_count > 2 {
-            break;
-        }
-        PLY += 2;
-        SEARCH_STACK[PLY as usize].pv = Some(child_pv);
-        BOARD.as_mut().unwrap().do_move(em.m);
-        let color: Color = BOARD.as_ref().unwrap().check_wld(&mut offset);
-        if color != Color::None {
-            BOARD.as_mut().unwrap().undo_move();
-            PLY -= 2;
-            if color == BOARD.as_ref().unwrap().side_to_move {
-                best_score = WIN_SCORE - PLY as Score - offset;
-                if pv_node {
-                    update_pv2(SEARCH_STACK[PLY as usize].pv.as_mut(), em.m);
-                }
+        loop {
+            em = mg.next_move(&mut self.board);
+            if em.m == MOVE_NONE {
                 break;
-            } else {
-                move_count -= 1;
-                continue;
             }
-        }
-        b4d = BOARD.as_ref().unwrap().defend_b4();
+            move_count += 1;
+            if !root_node && move_count > 2 {
+                break;
+            }
+            self.ply += 2;
+            self.search_stack[self.ply as usize].pv = Some(child_pv);
+            self.board.do_move(em.m);
+            let color: Color = self.board.check_wld(&mut offset);
+            if color != Color::None {
+                self.board.undo_move();
+                self.ply -= 2;
+                if color == self.board.side_to_move {
+                    best_score = WIN_SCORE - self.ply as Score - offset;
+                    if pv_node {
+                        update_pv2(self.search_stack[self.ply as usize].pv.as_mut(), em.m);
+                    }
+                    break;
+                } else {
+                    move_count -= 1;
+                    continue;
+                }
+            }
+            b4d = self.board.defend_b4();
 
-        BOARD.as_mut().unwrap().do_move(b4d);
+            self.board.do_move(b4d);
 
-        score = vcf(nt, depth - 2, false);
+            score = self.vcf(nt, depth - 2, false);
         
-        BOARD.as_mut().unwrap().undo_move();
-        BOARD.as_mut().unwrap().undo_move();
+            self.board.undo_move();
+            self.board.undo_move();
 
-        PLY -= 2;
-        if score > WIN_SCORE_THRESHOLD {
-            best_score = score;
-            if pv_node {
-                let cpv = SEARCH_STACK[PLY as usize + 2].pv;
-                update_pv4(SEARCH_STACK[PLY as usize].pv.as_mut(), em.m, cpv, b4d);
+            self.ply -= 2;
+            if score > WIN_SCORE_THRESHOLD {
+                best_score = score;
+                if pv_node {
+                    let cpv = self.search_stack[self.ply as usize + 2].pv;
+                    update_pv4(self.search_stack[self.ply as usize].pv.as_mut(), em.m, cpv, b4d);
+                }
+                break;
             }
-            break;
         }
+
+        best_score
     }
+}
 
-    best_score
+#[wasm_bindgen]
+pub fn make_move(r: i32, f: i32) -> Move {
+    _make_move(&r, &f)
+}
+
+#[wasm_bindgen]
+pub fn pass_move() -> Move {
+    MOVE_PASS
+}
+
+#[wasm_bindgen]
+pub fn rank_of(m: Move) -> i32 {
+    _rank_of(m)
+}
+
+#[wasm_bindgen]
+pub fn file_of(m: Move) -> i32 {
+    _file_of(m)
+}
+
+unsafe fn futility_margin(d: Depth) -> Score {
+    45 as Score * d as Score 
 }
 
 
 unsafe fn update_pv2(pv: Option<&mut Pv>, m0: Move) {
-    let mut pv_ptr = pv.unwrap_unchecked();
+    let pv_ptr = pv.unwrap_unchecked();
     pv_ptr[0] = m0;
     pv_ptr[1] = MOVE_NONE;
 }
 
 unsafe fn update_pv3(pv: Option<&mut Pv>, m0: Move, child_pv: Option<Pv>) {
     let mut i: usize = 0;
-    let mut pv_ptr = pv.unwrap_unchecked();
+    let pv_ptr = pv.unwrap_unchecked();
     let child_pv_ptr = child_pv.unwrap_unchecked();
     pv_ptr[0] = m0;
 
@@ -559,7 +1273,7 @@ unsafe fn update_pv3(pv: Option<&mut Pv>, m0: Move, child_pv: Option<Pv>) {
 
 unsafe fn update_pv4(pv: Option<&mut Pv>, m0: Move, child_pv: Option<Pv>, m1: Move) {
     let mut i: usize = 0;
-    let mut pv_ptr = pv.unwrap_unchecked();
+    let pv_ptr = pv.unwrap_unchecked();
     let child_pv_ptr = child_pv.unwrap_unchecked();
     pv_ptr[0] = m0;
     pv_ptr[1] = m1;
@@ -570,4 +1284,60 @@ unsafe fn update_pv4(pv: Option<&mut Pv>, m0: Move, child_pv: Option<Pv>, m1: Mo
     }
 
     pv_ptr[i + 2] = MOVE_NONE;
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_up_positions_are_not_judged() {
+        let mut engine: Engine = Engine::new();
+        // The last black stone in row order completes a double three
+        let moves: Vec<Move> = engine.set_position("o1o12/15/15/15/15/7x7/7x7/5xxx7/15/15/15/15/15/15/o1o12 w renju").unwrap();
+
+        assert_eq!(engine._check_wld_already(&moves), 0);
+        assert!(engine._game_end(&moves).is_none());
+
+
+        // Moves played after the set-up stones are still judged
+        let mut moves: Vec<Move> = engine.set_position("o1o12/15/15/15/15/7x7/7x7/5xx8/15/15/15/15/15/15/o1o12 b renju").unwrap();
+        moves.push(make_move(7, 7));
+        assert_eq!(engine._check_wld_already(&moves), 2);
+    }
+
+    #[test]
+    fn position_strings_count_the_moves() {
+        let mut engine: Engine = Engine::new();
+        let moves: Vec<Move> = vec![make_move(7, 7), make_move(8, 6)];
+        let s: String = engine._position_string(&moves);
+
+        assert_eq!(s, "15/15/15/15/15/15/15/7x7/6o8/15/15/15/15/15/15 b renju 3");
+        assert_eq!(engine.set_position(&s), Ok(moves.clone()));
+        assert_eq!(engine._position_string(&moves), s);
+    }
+
+    #[test]
+    fn stopped_searches_do_not_stop_the_next() {
+        let mut engine: Engine = Engine::new();
+        let moves: Vec<Move> = vec![make_move(7, 7), make_move(6, 7), make_move(8, 8)];
+
+        engine.replay(&moves);
+        engine.stop();
+        assert_eq!(engine.think(TIME_INFINITE).depth, 0);
+
+        assert_eq!(engine.think_limited(4, 0).depth, 4);
+        engine.stop();
+        assert!(engine._analyze(&moves, 1, 2).iter().all(|r| r.depth > 0));
+        engine.stop();
+        assert!(engine._think_and_move(&moves, 1).depth > 0);
+        engine.stop();
+        assert!(engine.think_with_clock(&TimeControl { time_left: 1000, ..TimeControl::default() }).depth > 0);
+
+        // The swap decision is reproducible, stopped before or not
+        let expected: SwapResult = Engine::new()._swap_move(&moves, false).unwrap();
+        engine.stop();
+        let result: SwapResult = engine._swap_move(&moves, false).unwrap();
+        assert_eq!((result.choice, result.moves, result.score), (expected.choice, expected.moves, expected.score));
+    }
+}
@@ -0,0 +1,161 @@
+use std::env;
+use std::fs;
+use std::process;
+use std::str::FromStr;
+
+use gomoku::notation;
+use gomoku::timeman::TimeControl;
+use gomoku::types::*;
+use gomoku::Engine;
+
+const USAGE: &str = "[--hash <mb>] [--side <n>] [--rule <freestyle|standard|renju|caro>] [--multipv <n>] [--time-left <ms>] [--increment <ms>] [--moves-to-go <n>] [--depth <d>] [--nodes <n>] [--swap <1|2>] [--psq <file>] [--position <string>] <seconds> [rank,file|h8i9...|pass ...]";
+
+fn parse_or_exit<T: FromStr>(what: &str, s: &str) -> T {
+    match s.parse() {
+        Ok(v) => v,
+        Err(_) => {
+            eprintln!("invalid {}: {}", what, s);
+            process::exit(2);
+        }
+    }
+}
+
+// Usage: gomoku [options] <seconds> [rank,file|h8i9...|pass ...]
+// Replays the given moves from an empty board and prints the engine's reply as rank,file.
+// Moves can also be written in board notation, several to an argument, as in h8i9j10.
+// "pass" hands the move to the other side without a stone.
+// With --multipv every line is printed as rank,file followed by its score.
+// With --swap the Swap1/Swap2 opening step is printed instead: "black", "white rank,file" or "place rank,file ...".
+// With --depth or --nodes the search is reproducible and <seconds> is ignored.
+// With --psq the moves of a Piskvork record are played before the given ones,
+// with --position (see src/position.rs) the position is set up before them, rule and size included.
+// With --time-left the move is budgeted from the game clock and <seconds> only caps it (0 for no cap).
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let mut engine = Engine::new();
+    let mut multi_pv: usize = 1;
+    let mut tc: TimeControl = Default::default();
+    let mut depth: Depth = 0;
+    let mut nodes: u64 = 0;
+    let mut swap: i32 = 0;
+    let mut psq_path: Option<String> = None;
+    let mut position: Option<String> = None;
+
+    while args.len() >= 3 && args[1].starts_with("--") {
+        match args[1].as_str() {
+            "--hash" => engine.set_hash_mb(parse_or_exit("hash size", &args[2])),
+            "--side" => {
+                if !engine.set_board_side(parse_or_exit("board side", &args[2])) {
+                    eprintln!("board side must be between {} and {}", BOARD_SIDE_MIN, BOARD_SIDE_MAX);
+                    process::exit(2);
+                }
+            },
+            "--rule" => engine.set_rule(parse_or_exit("rule", &args[2])),
+            "--multipv" => multi_pv = parse_or_exit("multipv", &args[2]),
+            "--time-left" => tc.time_left = parse_or_exit("time left", &args[2]),
+            "--increment" => tc.increment = parse_or_exit("increment", &args[2]),
+            "--moves-to-go" => tc.moves_to_go = parse_or_exit("moves to go", &args[2]),
+            "--depth" => depth = parse_or_exit("depth", &args[2]),
+            "--nodes" => nodes = parse_or_exit("node count", &args[2]),
+            "--swap" => swap = parse_or_exit("swap rule", &args[2]),
+            "--psq" => psq_path = Some(args[2].clone()),
+            "--position" => position = Some(args[2].clone()),
+            _ => {
+                eprintln!("unknown option: {}", args[1]);
+                process::exit(2);
+            }
+        }
+        args.drain(1..3);
+    }
+
+    if args.len() < 2 {
+        eprintln!("usage: {} {}", args[0], USAGE);
+        process::exit(2);
+    }
+
+    let tl: i32 = parse_or_exit("time limit", &args[1]);
+
+    let mut moves: Vec<Move> = Vec::new();
+    if let Some(s) = position {
+        match engine.set_position(&s) {
+            Ok(ms) => {
+                engine.reset();
+                moves = ms;
+            },
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(2);
+            }
+        }
+    }
+    if let Some(path) = psq_path {
+        let game = fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|s| engine._load_psq(&s));
+        match game {
+            Ok(game) => {
+                // The record was replayed to check it; every mode below starts from the empty board
+                engine.reset();
+                moves = game.moves;
+            },
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                process::exit(2);
+            }
+        }
+    }
+    for arg in args[2..].iter() {
+        if !arg.contains(',') {
+            match notation::parse_moves(arg, engine.board_side()) {
+                Ok(ms) => moves.extend(ms),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(2);
+                }
+            }
+            continue;
+        }
+        let rf: Vec<i32> = arg.split(',').filter_map(|x| x.trim().parse().ok()).collect();
+        if rf.len() != 2 || rf.iter().any(|x| *x < 0 || *x >= engine.board_side()) {
+            eprintln!("invalid move: {}", arg);
+            process::exit(2);
+        }
+        moves.push(_make_move(&rf[0], &rf[1]));
+    }
+
+    engine.set_progress(Some(Box::new(|r: &SearchResult| {
+        eprintln!("depth {} seldepth {} score {} nodes {} time {:.0} pv {:?}", r.depth, r.seldepth, r.score, r.nodes, r.elapsed, r.pv);
+    })));
+
+    if swap > 0 {
+        match engine._swap_move(&moves, swap == 2) {
+            Some(result) => {
+                let mut out: Vec<String> = vec![format!("{:?}", result.choice).to_lowercase()];
+                out.extend(result.moves.iter().map(|rf| format!("{},{}", rf[0], rf[1])));
+                println!("{}", out.join(" "));
+            },
+            None => {
+                eprintln!("no swap decision is made after {} stones", moves.len());
+                process::exit(2);
+            }
+        }
+    } else if multi_pv > 1 {
+        for result in engine._analyze(&moves, tl, multi_pv).iter() {
+            println!("{},{} {}", _rank_of(result.best_move), _file_of(result.best_move), result.score);
+        }
+    } else if depth > 0 || nodes > 0 {
+        for m in moves.iter() {
+            engine.do_move(*m);
+        }
+        let result = engine.think_limited(depth, nodes);
+        println!("{},{}", _rank_of(result.best_move), _file_of(result.best_move));
+    } else if tc.time_left > 0 {
+        tc.move_time = tl.saturating_mul(1000);
+        for m in moves.iter() {
+            engine.do_move(*m);
+        }
+        let result = engine.think_with_clock(&tc);
+        println!("{},{}", _rank_of(result.best_move), _file_of(result.best_move));
+    } else {
+        let result = engine._think_and_move(&moves, tl);
+        println!("{},{}", _rank_of(result.best_move), _file_of(result.best_move));
+    }
+}
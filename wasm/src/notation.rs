@@ -0,0 +1,111 @@
+// Board notation as used in renju books: a file letter from 'a' and a row number from 1 at the bottom,
+// so that h8 is the centre of the 15x15 board. Boards up to BOARD_SIDE_MAX use the letters 'a' to 't'.
+
+use crate::types::*;
+
+const PASS: &str = "pass";
+
+pub fn move_to_string(m: Move, side: i32) -> String {
+    if m == MOVE_PASS {
+        return PASS.to_string();
+    }
+
+    format!("{}{}", (b'a' + _file_of(m) as u8) as char, side - _rank_of(m))
+}
+
+pub fn moves_to_string(ms: &[Move], side: i32) -> String {
+    ms.iter().map(|m| move_to_string(*m, side)).collect()
+}
+
+// One move such as "h8" or "pass", in either case
+pub fn parse_move(s: &str, side: i32) -> Result<Move, String> {
+    match parse_moves(s, side)?.as_slice() {
+        [m] => Ok(*m),
+        _ => Err(format!("not a single move: {}", s)),
+    }
+}
+
+// A move sequence such as "h8i9j10"; spaces and commas between moves are ignored
+pub fn parse_moves(s: &str, side: i32) -> Result<Vec<Move>, String> {
+    let lower: String = s.to_ascii_lowercase();
+    let bytes: &[u8] = lower.as_bytes();
+    let mut moves: Vec<Move> = Vec::new();
+    let mut i: usize = 0;
+
+    while i < bytes.len() {
+        let c: u8 = bytes[i];
+
+        if c.is_ascii_whitespace() || c == b',' {
+            i += 1;
+            continue;
+        }
+        if lower[i..].starts_with(PASS) {
+            moves.push(MOVE_PASS);
+            i += PASS.len();
+            continue;
+        }
+        if !c.is_ascii_lowercase() {
+            return Err(format!("invalid character '{}' in moves", lower[i..].chars().next().unwrap_or('?')));
+        }
+
+        let digits: usize = bytes[i + 1..].iter().take_while(|b| b.is_ascii_digit()).count();
+        let f: i32 = c as i32 - b'a' as i32;
+        let row: i32 = lower[i + 1..i + 1 + digits].parse().unwrap_or(0);
+
+        if f >= side || row < 1 || row > side {
+            return Err(format!("invalid move at \"{}\" for a {}x{} board", &s[i..i + 1 + digits], side, side));
+        }
+
+        moves.push(_make_move(&(side - row), &f));
+        i += 1 + digits;
+    }
+
+    Ok(moves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn centre_and_corners() {
+        assert_eq!(parse_move("h8", 15), Ok(_make_move(&7, &7)));
+        assert_eq!(parse_move("A1", 15), Ok(_make_move(&14, &0)));
+        assert_eq!(parse_move("o15", 15), Ok(_make_move(&0, &14)));
+        assert_eq!(parse_move("t20", 20), Ok(_make_move(&0, &19)));
+        assert_eq!(move_to_string(_make_move(&7, &7), 15), "h8");
+        assert_eq!(move_to_string(_make_move(&19, &0), 20), "a1");
+    }
+
+    #[test]
+    fn every_square_round_trips() {
+        for side in [5, 15, 20] {
+            for r in 0..side {
+                for f in 0..side {
+                    let m: Move = _make_move(&r, &f);
+                    assert_eq!(parse_move(&move_to_string(m, side), side), Ok(m));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sequences() {
+        let moves: Vec<Move> = vec![_make_move(&7, &7), _make_move(&6, &8), MOVE_PASS, _make_move(&5, &9)];
+
+        assert_eq!(parse_moves("h8i9passj10", 15), Ok(moves.clone()));
+        assert_eq!(parse_moves(" H8, I9 PASS j10 ", 15), Ok(moves.clone()));
+        assert_eq!(moves_to_string(&moves, 15), "h8i9passj10");
+        assert_eq!(parse_moves("", 15), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn bad_moves() {
+        for s in ["p1", "a16", "a0", "h", "8", "h8-i9", "a99999999999"] {
+            assert!(parse_moves(s, 15).is_err(), "{}", s);
+        }
+        assert!(parse_move("h8i9", 15).is_err());
+        assert!(parse_move("", 15).is_err());
+        assert!(parse_move("t20", 19).is_err());
+    }
+}
@@ -0,0 +1,509 @@
+// Professional renju opening rules, played over a `Board` until free play begins.
+// See https://www.renju.net/rifrules/ for the rule texts.
+
+use std::cmp;
+use std::collections::VecDeque;
+
+use serde::Serialize;
+use strum_macros::EnumString;
+
+use crate::types::*;
+
+// Opening rules are only defined for the 15x15 renju board
+const SIDE: i32 = 15;
+
+#[derive(EnumString, Clone, Copy, PartialEq, Debug)]
+#[strum(ascii_case_insensitive)]
+pub enum OpeningRule {
+    Rif,
+    Yamaguchi,
+    Taraguchi10,
+    Soosorv8,
+}
+
+// The first player is the tentative Black
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Player {
+    First,
+    Second,
+}
+
+impl Player {
+    fn other(&self) -> Player {
+        match self {
+            Player::First => Player::Second,
+            Player::Second => Player::First,
+        }
+    }
+}
+
+// What has to be decided next. `area` is the half side of the central square a stone must go in,
+// None for anywhere on the board.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    Place { area: Option<i32> },
+    DeclareFifths { max: usize },
+    Swap,
+    ProposeFifths(usize),
+    ChooseFifth,
+    // Taraguchi: either one fifth move inside `area`, answered by a swap, or `count` alternatives anywhere
+    PlaceOrProposeFifths { area: i32, count: usize },
+    Free,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    // One of the first three stones, all placed by the tentative Black
+    Pattern(i32),
+    Place(Option<i32>),
+    Declare(Color, usize),
+    Swap,
+    // 0 stands for the declared number
+    Propose(usize),
+    Choose,
+    PlaceOrPropose(i32, usize),
+}
+
+const YAMAGUCHI_FIFTHS_MAX: usize = 10;
+const SOOSORV_FIFTHS_MAX: usize = 8;
+const TARAGUCHI_FIFTHS: usize = 10;
+const RIF_FIFTHS: usize = 2;
+
+// Image (rank, file) of the offset (dr, df) from the centre under a reflection or rotation of the board
+type Symmetry = fn(i32, i32) -> (i32, i32);
+
+const SYMMETRIES: [Symmetry; 8] = [
+    |r, f| (r, f),
+    |r, f| (r, -f),
+    |r, f| (-r, f),
+    |r, f| (-r, -f),
+    |r, f| (f, r),
+    |r, f| (f, -r),
+    |r, f| (-f, r),
+    |r, f| (-f, -r),
+];
+
+// What the caller needs to go on with the opening
+#[derive(Clone, Serialize)]
+pub struct OpeningState {
+    pub player: Player,
+    pub decision: Decision,
+    // Who plays Black for now
+    pub black: Player,
+    pub moves: Vec<Move>,
+    pub fifths: Vec<Move>,
+}
+
+pub struct Opening {
+    rule: OpeningRule,
+    steps: VecDeque<Step>,
+    moves: Vec<Move>,
+    black: Player,
+    fifth_count: usize,
+    fifths: Vec<Move>,
+}
+
+impl Opening {
+    // Starts a new game on `bd`, which is cleared; it has to be 15x15
+    pub fn new(rule: OpeningRule, bd: &mut Board) -> Result<Opening, String> {
+        if bd.side() != SIDE {
+            return Err(format!("opening rules are played on the {0}x{0} board", SIDE));
+        }
+
+        let steps: Vec<Step> = match rule {
+            OpeningRule::Rif => vec![
+                Step::Pattern(0), Step::Pattern(1), Step::Pattern(2), Step::Swap,
+                Step::Place(None), Step::Propose(RIF_FIFTHS), Step::Choose,
+            ],
+            OpeningRule::Yamaguchi => vec![
+                Step::Pattern(0), Step::Pattern(1), Step::Pattern(2), Step::Declare(Color::Black, YAMAGUCHI_FIFTHS_MAX), Step::Swap,
+                Step::Place(None), Step::Propose(0), Step::Choose,
+            ],
+            OpeningRule::Taraguchi10 => vec![
+                Step::Place(Some(0)), Step::Swap, Step::Place(Some(1)), Step::Swap, Step::Place(Some(2)), Step::Swap, Step::Place(Some(3)), Step::Swap,
+                Step::PlaceOrPropose(4, TARAGUCHI_FIFTHS),
+            ],
+            OpeningRule::Soosorv8 => vec![
+                Step::Pattern(0), Step::Pattern(1), Step::Pattern(2), Step::Swap,
+                Step::Place(None), Step::Declare(Color::White, SOOSORV_FIFTHS_MAX), Step::Swap, Step::Propose(0), Step::Choose,
+            ],
+        };
+
+        bd.reset();
+
+        Ok(Self {
+            rule,
+            steps: steps.into_iter().collect(),
+            moves: Vec::new(),
+            black: Player::First,
+            fifth_count: 0,
+            fifths: Vec::new(),
+        })
+    }
+
+    pub fn rule(&self) -> OpeningRule {
+        self.rule
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    // Proposed fifth moves while White is choosing
+    pub fn fifths(&self) -> &[Move] {
+        &self.fifths
+    }
+
+    pub fn black(&self) -> Player {
+        self.black
+    }
+
+    pub fn is_free(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    // The decision to make next and the player who makes it
+    pub fn next(&self) -> (Player, Decision) {
+        let step: Step = match self.steps.front() {
+            Some(step) => *step,
+            None => return (self.holder(self.color_to_move()), Decision::Free),
+        };
+
+        match step {
+            Step::Pattern(area) => (self.black, Decision::Place { area: Some(area) }),
+            Step::Place(area) => (self.holder(self.color_to_move()), Decision::Place { area }),
+            Step::Declare(c, max) => (self.holder(c), Decision::DeclareFifths { max }),
+            // The player whose colour did not place the last stone may swap
+            Step::Swap => (self.holder(self.color_to_move()), Decision::Swap),
+            Step::Propose(n) => (self.holder(Color::Black), Decision::ProposeFifths(if n == 0 {self.fifth_count} else {n})),
+            Step::Choose => (self.holder(Color::White), Decision::ChooseFifth),
+            Step::PlaceOrPropose(area, count) => (self.holder(Color::Black), Decision::PlaceOrProposeFifths { area, count }),
+        }
+    }
+
+    pub fn state(&self) -> OpeningState {
+        let (player, decision) = self.next();
+
+        OpeningState {
+            player,
+            decision,
+            black: self.black,
+            moves: self.moves.clone(),
+            fifths: self.fifths.clone(),
+        }
+    }
+
+    // Plays an opening stone, or the Taraguchi fifth move inside the central square
+    pub fn play(&mut self, bd: &mut Board, m: Move) -> Result<(), String> {
+        let area: i32 = match self.steps.front() {
+            Some(Step::Pattern(area)) | Some(Step::Place(Some(area))) | Some(Step::PlaceOrPropose(area, _)) => *area,
+            Some(Step::Place(None)) => bd.side(),
+            _ => return Err(self.unexpected("a stone")),
+        };
+
+        if !bd.contains(m) || !bd.is_empty(m) {
+            return Err(format!("{},{} is not an empty square", _rank_of(m), _file_of(m)));
+        }
+        if Self::distance_from_center(bd, m) > area {
+            return Err(format!("{},{} is outside the central {}x{} square", _rank_of(m), _file_of(m), 2 * area + 1, 2 * area + 1));
+        }
+
+        let step: Option<Step> = self.steps.pop_front();
+        if let Some(Step::PlaceOrPropose(..)) = step {
+            self.steps.push_front(Step::Swap);
+        }
+
+        bd.do_move(m);
+        self.moves.push(m);
+
+        Ok(())
+    }
+
+    pub fn declare_fifths(&mut self, n: usize) -> Result<(), String> {
+        let max: usize = match self.steps.front() {
+            Some(Step::Declare(_, max)) => *max,
+            _ => return Err(self.unexpected("the number of fifth moves")),
+        };
+
+        if n < 1 || n > max {
+            return Err(format!("the number of fifth moves must be between 1 and {}", max));
+        }
+
+        self.steps.pop_front();
+        self.fifth_count = n;
+
+        Ok(())
+    }
+
+    pub fn swap(&mut self, swap: bool) -> Result<(), String> {
+        if self.steps.front() != Some(&Step::Swap) {
+            return Err(self.unexpected("a swap decision"));
+        }
+
+        self.steps.pop_front();
+        if swap {
+            self.black = self.black.other();
+        }
+
+        Ok(())
+    }
+
+    // Black offers fifth moves, no two of which are the same up to symmetry of the position
+    pub fn propose_fifths(&mut self, bd: &Board, ms: &[Move]) -> Result<(), String> {
+        let n: usize = match self.next().1 {
+            Decision::ProposeFifths(n) | Decision::PlaceOrProposeFifths { count: n, .. } => n,
+            _ => return Err(self.unexpected("fifth moves")),
+        };
+
+        if ms.len() != n {
+            return Err(format!("{} fifth moves are required", n));
+        }
+
+        for (i, m) in ms.iter().enumerate() {
+            if !bd.contains(*m) || !bd.is_empty(*m) {
+                return Err(format!("{},{} is not an empty square", _rank_of(*m), _file_of(*m)));
+            }
+            if let Some(o) = ms[..i].iter().find(|o| self.is_symmetric(bd, **o, *m)) {
+                return Err(format!("{},{} is symmetric to {},{}", _rank_of(*m), _file_of(*m), _rank_of(*o), _file_of(*o)));
+            }
+        }
+
+        self.steps.pop_front();
+        if self.steps.front() != Some(&Step::Choose) {
+            self.steps.push_front(Step::Choose);
+        }
+        self.fifths = ms.to_vec();
+
+        Ok(())
+    }
+
+    // White picks one of the proposed fifth moves, which is then played
+    pub fn choose_fifth(&mut self, bd: &mut Board, m: Move) -> Result<(), String> {
+        if self.steps.front() != Some(&Step::Choose) {
+            return Err(self.unexpected("a fifth move choice"));
+        }
+        if !self.fifths.contains(&m) {
+            return Err(format!("{},{} was not proposed", _rank_of(m), _file_of(m)));
+        }
+
+        self.steps.pop_front();
+        self.fifths.clear();
+        bd.do_move(m);
+        self.moves.push(m);
+
+        Ok(())
+    }
+
+    fn color_to_move(&self) -> Color {
+        if self.moves.len().is_multiple_of(2) {Color::Black} else {Color::White}
+    }
+
+    fn holder(&self, c: Color) -> Player {
+        if c == Color::Black {self.black} else {self.black.other()}
+    }
+
+    fn unexpected(&self, what: &str) -> String {
+        format!("expected {:?}, not {}", self.next().1, what)
+    }
+
+    fn distance_from_center(bd: &Board, m: Move) -> i32 {
+        let c: i32 = bd.side() / 2;
+        cmp::max((_rank_of(m) - c).abs(), (_file_of(m) - c).abs())
+    }
+
+    // Whether a symmetry keeping every stone in place maps `a` onto `b`
+    fn is_symmetric(&self, bd: &Board, a: Move, b: Move) -> bool {
+        let c: i32 = bd.side() / 2;
+        let image = |t: &Symmetry, m: Move| -> Move {
+            let (r, f) = t(_rank_of(m) - c, _file_of(m) - c);
+            _make_move(&(r + c), &(f + c))
+        };
+
+        SYMMETRIES.iter().any(|t| {
+            image(t, a) == b && self.moves.iter().enumerate().all(|(i, m)| {
+                let j: Option<usize> = self.moves.iter().position(|x| *x == image(t, *m));
+                j.is_some_and(|j| j % 2 == i % 2)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mv(r: i32, f: i32) -> Move {
+        _make_move(&r, &f)
+    }
+
+    fn start(rule: OpeningRule) -> (Opening, Board) {
+        let mut bd: Board = Board::new();
+        let op: Opening = Opening::new(rule, &mut bd).unwrap();
+        (op, bd)
+    }
+
+    // The first three stones down the centre file, placed by the first player
+    fn play_pattern(op: &mut Opening, bd: &mut Board) {
+        assert_eq!(op.next(), (Player::First, Decision::Place { area: Some(0) }));
+        assert!(op.play(bd, mv(7, 6)).is_err());
+        op.play(bd, mv(7, 7)).unwrap();
+        assert_eq!(op.next(), (Player::First, Decision::Place { area: Some(1) }));
+        assert!(op.play(bd, mv(7, 7)).is_err());
+        op.play(bd, mv(6, 7)).unwrap();
+        assert_eq!(op.next(), (Player::First, Decision::Place { area: Some(2) }));
+        assert!(op.play(bd, mv(4, 7)).is_err());
+        op.play(bd, mv(5, 7)).unwrap();
+    }
+
+    #[test]
+    fn rif() {
+        let (mut op, mut bd) = start(OpeningRule::Rif);
+        assert!(op.swap(true).is_err());
+        assert!(op.declare_fifths(2).is_err());
+
+        play_pattern(&mut op, &mut bd);
+        assert_eq!(op.next(), (Player::Second, Decision::Swap));
+        assert!(op.play(&mut bd, mv(9, 7)).is_err());
+        op.swap(true).unwrap();
+        assert_eq!(op.black(), Player::Second);
+
+        assert_eq!(op.next(), (Player::First, Decision::Place { area: None }));
+        op.play(&mut bd, mv(9, 7)).unwrap();
+
+        assert_eq!(op.next(), (Player::Second, Decision::ProposeFifths(2)));
+        assert!(op.propose_fifths(&bd, &[mv(7, 6)]).is_err());
+        assert!(op.propose_fifths(&bd, &[mv(7, 6), mv(7, 8)]).is_err());
+        assert!(op.propose_fifths(&bd, &[mv(7, 6), mv(9, 7)]).is_err());
+        op.propose_fifths(&bd, &[mv(7, 6), mv(8, 6)]).unwrap();
+        assert_eq!(op.fifths(), &[mv(7, 6), mv(8, 6)]);
+
+        assert_eq!(op.next(), (Player::First, Decision::ChooseFifth));
+        assert!(op.choose_fifth(&mut bd, mv(7, 8)).is_err());
+        op.choose_fifth(&mut bd, mv(8, 6)).unwrap();
+
+        assert!(op.is_free());
+        assert_eq!(op.next(), (Player::First, Decision::Free));
+        assert_eq!(op.moves(), &[mv(7, 7), mv(6, 7), mv(5, 7), mv(9, 7), mv(8, 6)]);
+        assert!(op.fifths().is_empty());
+        assert!(bd.stone(mv(8, 6)) == Color::Black);
+        assert!(op.play(&mut bd, mv(0, 0)).is_err());
+    }
+
+    #[test]
+    fn yamaguchi() {
+        let (mut op, mut bd) = start(OpeningRule::Yamaguchi);
+
+        play_pattern(&mut op, &mut bd);
+        assert_eq!(op.next(), (Player::First, Decision::DeclareFifths { max: 10 }));
+        assert!(op.swap(false).is_err());
+        assert!(op.declare_fifths(0).is_err());
+        assert!(op.declare_fifths(11).is_err());
+        op.declare_fifths(3).unwrap();
+
+        assert_eq!(op.next(), (Player::Second, Decision::Swap));
+        op.swap(false).unwrap();
+        assert_eq!(op.next(), (Player::Second, Decision::Place { area: None }));
+        op.play(&mut bd, mv(9, 7)).unwrap();
+
+        assert_eq!(op.next(), (Player::First, Decision::ProposeFifths(3)));
+        assert!(op.propose_fifths(&bd, &[mv(7, 6), mv(8, 6)]).is_err());
+        assert!(op.propose_fifths(&bd, &[mv(7, 6), mv(8, 6), mv(8, 8)]).is_err());
+        op.propose_fifths(&bd, &[mv(7, 6), mv(8, 6), mv(0, 0)]).unwrap();
+
+        assert_eq!(op.next(), (Player::Second, Decision::ChooseFifth));
+        op.choose_fifth(&mut bd, mv(0, 0)).unwrap();
+        assert_eq!(op.next(), (Player::Second, Decision::Free));
+    }
+
+    // Stones leaving no symmetry of the board, so that any ten fifth moves are different
+    fn play_taraguchi(op: &mut Opening, bd: &mut Board) {
+        assert_eq!(op.next(), (Player::First, Decision::Place { area: Some(0) }));
+        op.play(bd, mv(7, 7)).unwrap();
+        assert_eq!(op.next(), (Player::Second, Decision::Swap));
+        op.swap(true).unwrap();
+
+        assert_eq!(op.next(), (Player::First, Decision::Place { area: Some(1) }));
+        assert!(op.play(bd, mv(5, 7)).is_err());
+        op.play(bd, mv(6, 8)).unwrap();
+        assert_eq!(op.next(), (Player::Second, Decision::Swap));
+        op.swap(false).unwrap();
+
+        assert_eq!(op.next(), (Player::Second, Decision::Place { area: Some(2) }));
+        assert!(op.play(bd, mv(4, 7)).is_err());
+        op.play(bd, mv(8, 9)).unwrap();
+        assert_eq!(op.next(), (Player::First, Decision::Swap));
+        op.swap(false).unwrap();
+
+        assert_eq!(op.next(), (Player::First, Decision::Place { area: Some(3) }));
+        assert!(op.play(bd, mv(3, 7)).is_err());
+        op.play(bd, mv(5, 5)).unwrap();
+        assert_eq!(op.next(), (Player::Second, Decision::Swap));
+        op.swap(false).unwrap();
+
+        assert_eq!(op.next(), (Player::Second, Decision::PlaceOrProposeFifths { area: 4, count: 10 }));
+    }
+
+    #[test]
+    fn taraguchi10_fifth_move_in_the_centre() {
+        let (mut op, mut bd) = start(OpeningRule::Taraguchi10);
+
+        play_taraguchi(&mut op, &mut bd);
+        assert!(op.play(&mut bd, mv(2, 7)).is_err());
+        op.play(&mut bd, mv(9, 5)).unwrap();
+
+        assert_eq!(op.next(), (Player::First, Decision::Swap));
+        op.swap(true).unwrap();
+        assert_eq!(op.black(), Player::First);
+        assert_eq!(op.next(), (Player::Second, Decision::Free));
+    }
+
+    #[test]
+    fn taraguchi10_ten_fifth_moves() {
+        let (mut op, mut bd) = start(OpeningRule::Taraguchi10);
+
+        play_taraguchi(&mut op, &mut bd);
+        let fifths: Vec<Move> = (0..10).map(|f| mv(0, f)).collect();
+        assert!(op.propose_fifths(&bd, &fifths[..9]).is_err());
+        op.propose_fifths(&bd, &fifths).unwrap();
+
+        assert_eq!(op.next(), (Player::First, Decision::ChooseFifth));
+        assert!(op.choose_fifth(&mut bd, mv(0, 10)).is_err());
+        op.choose_fifth(&mut bd, mv(0, 9)).unwrap();
+        assert_eq!(op.next(), (Player::First, Decision::Free));
+        assert_eq!(op.moves().len(), 5);
+    }
+
+    #[test]
+    fn soosorv8() {
+        let (mut op, mut bd) = start(OpeningRule::Soosorv8);
+
+        play_pattern(&mut op, &mut bd);
+        op.swap(false).unwrap();
+        assert_eq!(op.next(), (Player::Second, Decision::Place { area: None }));
+        op.play(&mut bd, mv(9, 7)).unwrap();
+
+        assert_eq!(op.next(), (Player::Second, Decision::DeclareFifths { max: 8 }));
+        assert!(op.declare_fifths(9).is_err());
+        op.declare_fifths(2).unwrap();
+
+        assert_eq!(op.next(), (Player::First, Decision::Swap));
+        op.swap(true).unwrap();
+        assert_eq!(op.next(), (Player::Second, Decision::ProposeFifths(2)));
+        assert!(op.choose_fifth(&mut bd, mv(7, 6)).is_err());
+        op.propose_fifths(&bd, &[mv(7, 6), mv(8, 6)]).unwrap();
+
+        assert_eq!(op.next(), (Player::First, Decision::ChooseFifth));
+        op.choose_fifth(&mut bd, mv(7, 6)).unwrap();
+        assert!(op.is_free());
+    }
+
+    #[test]
+    fn other_sizes_are_rejected() {
+        let mut bd: Board = Board::new();
+        assert!(bd.set_side(16));
+
+        assert!(Opening::new(OpeningRule::Rif, &mut bd).is_err());
+    }
+}
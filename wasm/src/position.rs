@@ -0,0 +1,226 @@
+// One-line positions, in the manner of chess FEN:
+//
+//   15/15/15/15/15/15/15/7x7/6o8/15/15/15/15/15/15 b renju 3
+//
+// The rows from the top separated by '/', in each of them 'x' for black, 'o' for white and a number for
+// that many empty squares; the number of rows is the board side. Then the side to move ('b' or 'w'),
+// the rule, and optionally the number of the next move.
+
+use std::fmt;
+
+use crate::types::*;
+
+#[derive(Clone)]
+pub struct Position {
+    pub side: i32,
+    pub rule: Rule,
+    pub black: Vec<Move>,
+    pub white: Vec<Move>,
+    pub side_to_move: Color,
+    pub move_number: Option<u32>,
+}
+
+impl Position {
+    pub fn parse(s: &str) -> Result<Position, String> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() < 3 || fields.len() > 4 {
+            return Err(format!("expected rows, side to move, rule and an optional move number: {}", s));
+        }
+
+        let rows: Vec<&str> = fields[0].split('/').collect();
+        let side: i32 = rows.len() as i32;
+        if !is_ok_side(side) {
+            return Err(format!("unsupported board size {}x{}", side, side));
+        }
+
+        let mut pos: Position = Position {
+            side,
+            rule: fields[2].parse().map_err(|_| format!("unknown rule: {}", fields[2]))?,
+            black: Vec::new(),
+            white: Vec::new(),
+            side_to_move: match fields[1] {
+                "b" => Color::Black,
+                "w" => Color::White,
+                _ => return Err(format!("side to move must be b or w, not {}", fields[1])),
+            },
+            move_number: match fields.get(3) {
+                Some(n) => Some(n.parse().map_err(|_| format!("invalid move number: {}", n))?),
+                None => None,
+            },
+        };
+
+        for (r, row) in rows.iter().enumerate() {
+            let mut f: i32 = 0;
+            let mut empty: String = String::new();
+
+            for c in row.chars().chain(std::iter::once('/')) {
+                if c.is_ascii_digit() {
+                    empty.push(c);
+                    continue;
+                }
+                if !empty.is_empty() {
+                    let n: i32 = empty.parse().map_err(|_| format!("invalid number of empty squares {} in row {}", empty, r + 1))?;
+                    if n > side - f {
+                        return Err(format!("row {} has more than {} squares", r + 1, side));
+                    }
+                    f += n;
+                    empty.clear();
+                }
+
+                match c {
+                    'x' => pos.black.push(_make_move(&(r as i32), &f)),
+                    'o' => pos.white.push(_make_move(&(r as i32), &f)),
+                    '/' => break,
+                    _ => return Err(format!("invalid character '{}' in row {}", c, r + 1)),
+                }
+                f += 1;
+            }
+
+            if f != side {
+                return Err(format!("row {} has {} squares instead of {}", r + 1, f, side));
+            }
+        }
+
+        Ok(pos)
+    }
+
+    // The stones on `bd`
+    pub fn from_board(bd: &Board) -> Position {
+        let squares: Vec<Move> = (0..bd.side()).flat_map(|r| (0..bd.side()).map(move |f| _make_move(&r, &f))).collect();
+
+        Position {
+            side: bd.side(),
+            rule: bd.rule(),
+            black: squares.iter().cloned().filter(|m| bd.stone(*m) == Color::Black).collect(),
+            white: squares.iter().cloned().filter(|m| bd.stone(*m) == Color::White).collect(),
+            side_to_move: bd.side_to_move,
+            move_number: None,
+        }
+    }
+
+    // Sets `bd` to the position, rule and size included
+    pub fn to_board(&self, bd: &mut Board) -> Result<(), String> {
+        bd.set_rule(self.rule);
+        if !bd.set_side(self.side) || !bd.setup(&self.black, &self.white, self.side_to_move) {
+            return Err("stones must be on the board and not overlap".to_string());
+        }
+
+        Ok(())
+    }
+
+    // A move list reaching the position, with passes where one colour has more stones
+    pub fn moves(&self) -> Vec<Move> {
+        _setup_moves(&self.black, &self.white, self.side_to_move)
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        let mut rows: Vec<String> = Vec::new();
+
+        for r in 0..self.side {
+            let mut row: String = String::new();
+            let mut empty: i32 = 0;
+
+            for f in 0..self.side {
+                let m: Move = _make_move(&r, &f);
+                let c: Option<char> = if self.black.contains(&m) {Some('x')} else if self.white.contains(&m) {Some('o')} else {None};
+
+                match c {
+                    Some(c) => {
+                        if empty > 0 {
+                            row += &empty.to_string();
+                        }
+                        row.push(c);
+                        empty = 0;
+                    },
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                row += &empty.to_string();
+            }
+            rows.push(row);
+        }
+
+        write!(out, "{} {} {}", rows.join("/"), if self.side_to_move == Color::Black {"b"} else {"w"}, format!("{:?}", self.rule).to_lowercase())?;
+        if let Some(n) = self.move_number {
+            write!(out, " {}", n)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(s: &str) -> Position {
+        let pos: Position = Position::parse(s).unwrap();
+        assert_eq!(pos.to_string(), s);
+        pos
+    }
+
+    #[test]
+    fn parse_and_display() {
+        let pos: Position = round_trip("15/15/15/15/15/15/15/7x7/6o8/15/15/15/15/15/15 b renju");
+        assert_eq!(pos.side, 15);
+        assert!(pos.rule == Rule::Renju && pos.side_to_move == Color::Black);
+        assert_eq!(pos.black, vec![_make_move(&7, &7)]);
+        assert_eq!(pos.white, vec![_make_move(&8, &6)]);
+
+        let pos: Position = round_trip("x3o/5/2x2/5/o4 w caro");
+        assert_eq!(pos.side, 5);
+        assert_eq!(pos.black, vec![_make_move(&0, &0), _make_move(&2, &2)]);
+        assert_eq!(pos.white, vec![_make_move(&0, &4), _make_move(&4, &0)]);
+
+        let pos: Position = round_trip(&format!("{}/xo18/{}/18ox w freestyle", ["20"; 9].join("/"), ["20"; 9].join("/")));
+        assert_eq!(pos.side, 20);
+        assert_eq!(pos.white, vec![_make_move(&9, &1), _make_move(&19, &18)]);
+
+        // The number of the next move is optional
+        assert_eq!(pos.move_number, None);
+        let pos: Position = round_trip("15/15/15/15/15/15/15/7x7/6o8/15/15/15/15/15/15 b renju 3");
+        assert_eq!(pos.move_number, Some(3));
+        assert_eq!(round_trip("5/5/5/5/5 b renju 1").move_number, Some(1));
+
+        // Numbers may be split and rules written in any case
+        assert_eq!(Position::parse("2x1x/5/5/5/5 b Standard").unwrap().to_string(), "2x1x/5/5/5/5 b standard");
+    }
+
+    #[test]
+    fn board_round_trip() {
+        let pos: Position = Position::parse("x3o/5/2x2/5/o4 b standard").unwrap();
+        let mut bd: Board = Board::new();
+
+        pos.to_board(&mut bd).unwrap();
+        assert_eq!(bd.side(), 5);
+        assert!(bd.rule() == Rule::Standard && bd.side_to_move == Color::Black);
+        assert_eq!(Position::from_board(&bd).to_string(), pos.to_string());
+        assert_eq!(pos.moves(), vec![_make_move(&0, &0), _make_move(&0, &4), _make_move(&2, &2), _make_move(&4, &0)]);
+    }
+
+    #[test]
+    fn malformed() {
+        for s in [
+            "",
+            "5/5/5/5/5 b",
+            "5/5/5/5/5 b renju three",
+            "5/5/5/5/5 b renju -3",
+            "5/5/5/5/5 b renju 3 4",
+            "5/5/5/5 b renju",
+            "5/5/5/5/5/5/5/5/5/5/5/5/5/5/5/5/5/5/5/5/5 b renju",
+            "6/5/5/5/5 b renju",
+            "4/5/5/5/5 b renju",
+            "4xx/5/5/5/5 b renju",
+            "x99999999999/5/5/5/5 b renju",
+            "99999999999/5/5/5/5 b renju",
+            "2y2/5/5/5/5 b renju",
+            "5/5/5/5/5 x renju",
+            "5/5/5/5/5 b go",
+        ] {
+            assert!(Position::parse(s).is_err(), "{:?}", s);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+// Piskvork game records (.psq), as collected from Gomocup tournaments:
+//
+//   Piskvorky 15x15, 11:11, 0
+//   8,8,1200
+//   9,9,850
+//   ...
+//   pbrain-first.zip
+//   pbrain-second.zip
+//   -1
+//
+// Moves are x,y from 1 at the top left with the thinking time in milliseconds; whatever follows them is kept as is.
+
+use serde::Serialize;
+
+use crate::types::*;
+
+const HEADER: &str = "Piskvorky";
+
+#[derive(Clone, Serialize)]
+pub struct PsqGame {
+    pub side: i32,
+    pub moves: Vec<Move>,
+    // Milliseconds spent on each move
+    pub times: Vec<i32>,
+    // Lines after the moves, usually the engines that played
+    pub trailer: Vec<String>,
+}
+
+impl PsqGame {
+    pub fn new(side: i32, moves: &[Move]) -> PsqGame {
+        Self {
+            side,
+            moves: moves.to_vec(),
+            times: vec![0; moves.len()],
+            trailer: Vec::new(),
+        }
+    }
+}
+
+// Reads a game and replays it on `bd`, which is resized to the game and keeps its rule
+pub fn read_psq(s: &str, bd: &mut Board) -> Result<PsqGame, String> {
+    let mut lines = s.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).peekable();
+
+    let header: &str = lines.next().ok_or("empty record")?;
+    let side: i32 = parse_header(header).ok_or(format!("invalid header: {}", header))?;
+    if !bd.set_side(side) {
+        return Err(format!("unsupported board size {}x{}", side, side));
+    }
+
+    let mut game: PsqGame = PsqGame::new(side, &[]);
+
+    while let Some(line) = lines.peek() {
+        let (x, y, time) = match parse_move_line(line) {
+            Some(v) => v,
+            None => break,
+        };
+        if x < 1 || x > side || y < 1 || y > side {
+            return Err(format!("move {} at {},{} is off the board", game.moves.len() + 1, x, y));
+        }
+
+        let m: Move = _make_move(&(y - 1), &(x - 1));
+        bd.check_move(m).map_err(|e| format!("move {} at {},{} is {}", game.moves.len() + 1, x, y, e))?;
+
+        bd.do_move(m);
+        game.moves.push(m);
+        game.times.push(time);
+        lines.next();
+    }
+
+    game.trailer = lines.map(|l| l.to_string()).collect();
+
+    Ok(game)
+}
+
+// Fails on passes, which the format has no way to write
+pub fn write_psq(game: &PsqGame) -> Result<String, String> {
+    let mut out: String = format!("{} {}x{}, 11:11, 0\n", HEADER, game.side, game.side);
+
+    for (i, m) in game.moves.iter().enumerate() {
+        if *m == MOVE_PASS {
+            return Err(format!("move {} is a pass", i + 1));
+        }
+        out += &format!("{},{},{}\n", _file_of(*m) + 1, _rank_of(*m) + 1, game.times.get(i).cloned().unwrap_or(0));
+    }
+
+    for line in game.trailer.iter() {
+        out += line;
+        out += "\n";
+    }
+
+    Ok(out)
+}
+
+// "Piskvorky 15x15, 11:11, 0" gives 15; only square boards are played
+fn parse_header(line: &str) -> Option<i32> {
+    let size: &str = line.strip_prefix(HEADER)?.trim_start().split(',').next()?;
+    let (w, h) = size.split_once('x')?;
+    let (w, h): (i32, i32) = (w.trim().parse().ok()?, h.trim().parse().ok()?);
+
+    if w == h {Some(w)} else {None}
+}
+
+// "x,y,time", the time being optional
+fn parse_move_line(line: &str) -> Option<(i32, i32, i32)> {
+    let fields: Vec<i32> = line.split(',').map(|x| x.trim().parse().ok()).collect::<Option<Vec<i32>>>()?;
+
+    match fields.as_slice() {
+        [x, y] => Some((*x, *y, 0)),
+        [x, y, t] => Some((*x, *y, *t)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECORD: &str = "Piskvorky 15x15, 11:11, 0\n8,8,1200\n9,9,850\n9,8,0\npbrain-first.zip\npbrain-second.zip\n-1\n";
+
+    fn read(s: &str, rule: Rule) -> Result<PsqGame, String> {
+        let mut bd: Board = Board::new();
+        bd.set_rule(rule);
+        read_psq(s, &mut bd)
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut bd: Board = Board::new();
+        let game: PsqGame = read_psq(RECORD, &mut bd).unwrap();
+
+        assert_eq!(game.side, 15);
+        assert_eq!(game.moves, vec![_make_move(&7, &7), _make_move(&8, &8), _make_move(&7, &8)]);
+        assert_eq!(game.times, vec![1200, 850, 0]);
+        assert_eq!(game.trailer, vec!["pbrain-first.zip", "pbrain-second.zip", "-1"]);
+        assert_eq!(bd.piece_cnt, 3);
+        assert!(bd.side_to_move == Color::White);
+        assert_eq!(write_psq(&game), Ok(RECORD.to_string()));
+    }
+
+    #[test]
+    fn other_sizes_and_missing_times() {
+        let mut bd: Board = Board::new();
+        let game: PsqGame = read_psq("Piskvorky 20x20, 11:11, 0\n20,20\n1,1\n", &mut bd).unwrap();
+
+        assert_eq!(bd.side(), 20);
+        assert_eq!(game.moves, vec![_make_move(&19, &19), _make_move(&0, &0)]);
+        assert_eq!(game.times, vec![0, 0]);
+        assert!(game.trailer.is_empty());
+    }
+
+    #[test]
+    fn bad_records() {
+        for s in [
+            "",
+            "Gomoku 15x15, 11:11, 0\n",
+            "Piskvorky 15x20, 11:11, 0\n",
+            "Piskvorky 3x3, 11:11, 0\n",
+            "Piskvorky 15x15, 11:11, 0\n1,2049,0\n",
+            "Piskvorky 15x15, 11:11, 0\n0,5,0\n",
+            "Piskvorky 15x15, 11:11, 0\n16,1,0\n",
+            "Piskvorky 15x15, 11:11, 0\n8,8,0\n8,8,0\n",
+        ] {
+            assert!(read(s, Rule::Freestyle).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn moves_follow_the_rule() {
+        let five: &str = "Piskvorky 15x15, 11:11, 0\n1,1\n1,2\n2,1\n2,2\n3,1\n3,2\n4,1\n4,2\n5,1\n";
+        assert!(read(five, Rule::Freestyle).is_ok());
+        assert!(read(&format!("{}5,2\n", five), Rule::Freestyle).is_err());
+
+        // Black's third stone of the vertical and horizontal lines is a double three
+        let double_three: &str = "Piskvorky 15x15, 11:11, 0\n8,6\n1,1\n8,7\n1,3\n6,8\n1,5\n7,8\n1,7\n8,8\n";
+        assert!(read(double_three, Rule::Freestyle).is_ok());
+        assert!(read(double_three, Rule::Renju).is_err());
+    }
+
+    #[test]
+    fn passes_are_not_written() {
+        assert!(write_psq(&PsqGame::new(15, &[_make_move(&7, &7), MOVE_PASS])).is_err());
+    }
+}
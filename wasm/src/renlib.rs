@@ -0,0 +1,311 @@
+// RenLib opening libraries (.lib): a 20 byte header, then the move tree in preorder, two bytes a node.
+// The first byte is the square, 16 * row from the top + file from 1, 0 for no move; the second holds flags
+// telling whether the node has children (DOWN) and a next sibling (RIGHT), and what follows it:
+// a comment and, with the flag extension, a text to show on the board. Texts end with a zero byte and are
+// padded to an even length. RenLib only plays on the 15x15 board.
+
+use serde::{Deserialize, Serialize};
+
+use crate::notation::*;
+use crate::types::*;
+
+const MAGIC: [u8; 8] = [0xFF, b'R', b'e', b'n', b'L', b'i', b'b', 0xFF];
+const HEADER_SIZE: usize = 20;
+const VERSION_MAJOR: u8 = 3;
+const VERSION_MINOR: u8 = 0;
+const SIDE: i32 = 15;
+
+const DOWN: u32 = 0x80;
+const RIGHT: u32 = 0x40;
+const OLD_COMMENT: u32 = 0x20;
+const MARK: u32 = 0x10;
+const COMMENT: u32 = 0x08;
+const START: u32 = 0x04;
+const NO_MOVE: u32 = 0x02;
+const EXTENSION: u32 = 0x01;
+const BOARD_TEXT: u32 = 0x100;
+
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct LibNode {
+    // MOVE_NONE at the root, MOVE_PASS for a pass
+    pub m: Move,
+    pub comment: String,
+    pub board_text: String,
+    pub mark: bool,
+    // Where RenLib opens the library
+    pub start: bool,
+    pub children: Vec<LibNode>,
+}
+
+// Reads a library, replaying every branch on `bd` (cleared and set to 15x15, keeping its rule)
+// and failing on the first move to an occupied square, after the end of the game, or foul for Black
+pub fn read_lib(data: &[u8], bd: &mut Board) -> Result<LibNode, String> {
+    if data.len() < HEADER_SIZE || data[..MAGIC.len()] != MAGIC {
+        return Err("not a RenLib file".to_string());
+    }
+    if data[MAGIC.len()] < VERSION_MAJOR {
+        return Err(format!("RenLib version {} is not supported", data[MAGIC.len()]));
+    }
+    if !bd.set_side(SIDE) {
+        return Err("no 15x15 board".to_string());
+    }
+
+    let mut reader: Reader = Reader { data, pos: HEADER_SIZE };
+    let mut root: LibNode = LibNode::default();
+    // Parents still waiting for a next sibling, as paths of child indices from the root
+    let mut pending: Vec<Vec<usize>> = Vec::new();
+    let mut parent: Option<Vec<usize>> = Some(Vec::new());
+    let mut first: bool = true;
+
+    while let Some(path) = parent {
+        let (node, flags) = match reader.node()? {
+            Some(v) => v,
+            None => break,
+        };
+
+        // A first node without a move is the empty board itself
+        if first && node.m == MOVE_NONE {
+            first = false;
+            root = node;
+            parent = if flags & DOWN != 0 {Some(path)} else {None};
+            continue;
+        }
+        first = false;
+
+        let siblings: &mut Vec<LibNode> = &mut node_at(&mut root, &path).children;
+        siblings.push(LibNode { m: if node.m == MOVE_NONE {MOVE_PASS} else {node.m}, ..node });
+        let mut child: Vec<usize> = path.clone();
+        child.push(siblings.len() - 1);
+
+        if flags & RIGHT != 0 {
+            pending.push(path);
+        }
+        parent = if flags & DOWN != 0 {Some(child)} else {pending.pop()};
+    }
+
+    bd.reset();
+    check(&root, bd, &mut Vec::new())?;
+
+    Ok(root)
+}
+
+pub fn write_lib(root: &LibNode) -> Vec<u8> {
+    let mut out: Vec<u8> = MAGIC.to_vec();
+    out.push(VERSION_MAJOR);
+    out.push(VERSION_MINOR);
+    out.resize(HEADER_SIZE, 0xFF);
+
+    write_node(&mut out, root, false);
+
+    out
+}
+
+fn write_node(out: &mut Vec<u8>, node: &LibNode, right: bool) {
+    let mut flags: u32 = 0;
+    if !node.children.is_empty() { flags |= DOWN; }
+    if right { flags |= RIGHT; }
+    if node.mark { flags |= MARK; }
+    if !node.comment.is_empty() { flags |= COMMENT; }
+    if node.start { flags |= START; }
+    if node.m == MOVE_NONE || node.m == MOVE_PASS { flags |= NO_MOVE; }
+    if !node.board_text.is_empty() { flags |= BOARD_TEXT | EXTENSION; }
+
+    out.push(if flags & NO_MOVE != 0 {0} else {(16 * _rank_of(node.m) + _file_of(node.m) + 1) as u8});
+    out.push(flags as u8);
+    if flags & EXTENSION != 0 {
+        out.push((flags >> 16) as u8);
+        out.push((flags >> 8) as u8);
+    }
+    if flags & COMMENT != 0 {
+        write_text(out, &node.comment);
+    }
+    if flags & BOARD_TEXT != 0 {
+        write_text(out, &node.board_text);
+    }
+
+    for (i, child) in node.children.iter().enumerate() {
+        write_node(out, child, i + 1 < node.children.len());
+    }
+}
+
+// Texts are stored in Latin-1, RenLib being a Windows program of that era
+fn write_text(out: &mut Vec<u8>, s: &str) {
+    out.extend(s.chars().map(|c| if (c as u32) < 0x100 && c != '\0' {c as u8} else {b'?'}));
+    out.push(0);
+    if !out.len().is_multiple_of(2) {
+        out.push(0);
+    }
+}
+
+fn node_at<'a>(root: &'a mut LibNode, path: &[usize]) -> &'a mut LibNode {
+    path.iter().fold(root, |node, i| &mut node.children[*i])
+}
+
+// Plays every child of `node` from the position on `bd`, `line` being the moves that led there
+fn check(node: &LibNode, bd: &mut Board, line: &mut Vec<Move>) -> Result<(), String> {
+    for child in node.children.iter() {
+        let m: Move = child.m;
+
+        bd.check_move(m).map_err(|e| format!("{} after \"{}\" is {}", move_to_string(m, SIDE), moves_to_string(line, SIDE), e))?;
+
+        bd.do_move(m);
+        line.push(m);
+        let result: Result<(), String> = check(child, bd, line);
+        line.pop();
+        bd.undo_move();
+        result?;
+    }
+
+    Ok(())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    // The next node and its flags, None at the end of the data
+    fn node(&mut self) -> Result<Option<(LibNode, u32)>, String> {
+        if self.pos + 2 > self.data.len() {
+            return Ok(None);
+        }
+
+        let square: u8 = self.data[self.pos];
+        let mut flags: u32 = self.data[self.pos + 1] as u32;
+
+        let (r, f): (i32, i32) = ((square >> 4) as i32, (square & 0x0F) as i32 - 1);
+        if square != 0 && (f < 0 || r >= SIDE) {
+            return Err(format!("invalid square {:#04x} at offset {}", square, self.pos));
+        }
+        self.pos += 2;
+
+        if flags & EXTENSION != 0 {
+            let ext: &[u8] = self.bytes(2)?;
+            flags |= (ext[0] as u32) << 16 | (ext[1] as u32) << 8;
+        }
+
+        let mut node: LibNode = LibNode {
+            m: if square == 0 {MOVE_NONE} else {_make_move(&r, &f)},
+            mark: flags & MARK != 0,
+            start: flags & START != 0,
+            ..LibNode::default()
+        };
+        if flags & (COMMENT | OLD_COMMENT) != 0 {
+            node.comment = self.text()?;
+        }
+        if flags & BOARD_TEXT != 0 {
+            node.board_text = self.text()?;
+        }
+
+        Ok(Some((node, flags)))
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&[u8], String> {
+        if self.pos + n > self.data.len() {
+            return Err("unexpected end of file".to_string());
+        }
+        self.pos += n;
+
+        Ok(&self.data[self.pos - n..self.pos])
+    }
+
+    // Reads two bytes at a time up to the pair holding the terminating zero
+    fn text(&mut self) -> Result<String, String> {
+        let mut s: String = String::new();
+
+        loop {
+            let pair: &[u8] = self.bytes(2)?;
+            let (a, b) = (pair[0], pair[1]);
+
+            if a == 0 {
+                return Ok(s);
+            }
+            s.push(a as char);
+            if b == 0 {
+                return Ok(s);
+            }
+            s.push(b as char);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(m: &str, children: Vec<LibNode>) -> LibNode {
+        LibNode { m: parse_move(m, SIDE).unwrap(), children, ..LibNode::default() }
+    }
+
+    fn header() -> Vec<u8> {
+        let mut data: Vec<u8> = MAGIC.to_vec();
+        data.extend([3, 0]);
+        data.resize(HEADER_SIZE, 0xFF);
+        data
+    }
+
+    // Laid out as RenLib saves it: no root node, the first move straight after the header
+    #[test]
+    fn reads_a_library() {
+        let mut data: Vec<u8> = header();
+        data.extend([0x78, 0x80]);
+        data.extend([0x69, 0xC8]);
+        data.extend(b"Sure win\0\0");
+        data.extend([0x5A, 0x00]);
+        data.extend([0x89, 0x05, 0x00, 0x01]);
+        data.extend(b"A\0");
+
+        let mut bd: Board = Board::new();
+        let root: LibNode = read_lib(&data, &mut bd).unwrap();
+
+        let mut i9: LibNode = node("i9", vec![node("j10", vec![])]);
+        i9.comment = "Sure win".to_string();
+        let mut i7: LibNode = node("i7", vec![]);
+        i7.start = true;
+        i7.board_text = "A".to_string();
+
+        assert_eq!(root, LibNode { children: vec![node("h8", vec![i9, i7])], ..LibNode::default() });
+        assert_eq!(bd.piece_cnt, 0);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut h8: LibNode = node("h8", vec![node("i9", vec![]), node("i8", vec![node("g9", vec![])]), node("h9", vec![])]);
+        h8.comment = "Three replies".to_string();
+        h8.children[0].comment = "odd".to_string();
+        h8.children[0].board_text = "even".to_string();
+        h8.children[1].mark = true;
+        h8.children[1].children[0].board_text = "café".to_string();
+        h8.children[2].children.push(LibNode { m: MOVE_PASS, ..LibNode::default() });
+        let root: LibNode = LibNode { children: vec![h8, node("a1", vec![])], comment: "Root".to_string(), start: true, ..LibNode::default() };
+
+        let data: Vec<u8> = write_lib(&root);
+        assert!(data.len().is_multiple_of(2));
+
+        let mut bd: Board = Board::new();
+        assert_eq!(read_lib(&data, &mut bd), Ok(root.clone()));
+        assert_eq!(write_lib(&read_lib(&data, &mut bd).unwrap()), data);
+    }
+
+    #[test]
+    fn bad_libraries() {
+        let mut bd: Board = Board::new();
+        assert!(read_lib(b"RenLib", &mut bd).is_err());
+
+        let mut data: Vec<u8> = header();
+        data[MAGIC.len()] = 2;
+        assert!(read_lib(&data, &mut bd).is_err());
+
+        // An unterminated comment and squares off the board
+        for bytes in [&[0x78, 0x08, b'a', b'b'][..], &[0x10, 0x00][..], &[0xF1, 0x00][..]] {
+            let mut data: Vec<u8> = header();
+            data.extend(bytes);
+            assert!(read_lib(&data, &mut bd).is_err(), "{:?}", bytes);
+        }
+
+        // The same square twice in a line
+        let root: LibNode = LibNode { children: vec![node("h8", vec![node("h8", vec![])])], ..LibNode::default() };
+        assert!(read_lib(&write_lib(&root), &mut bd).is_err());
+    }
+}
@@ -0,0 +1,280 @@
+// Game archives of the Renju International Federation (https://www.renju.net/), an XML database:
+//
+//   <database>
+//     <players><player id="1" name="..." surname="..." country="7"/> ...</players>
+//     <tournaments><tournament id="3" name="..." year="2019" rule="1"/> ...</tournaments>
+//     <games>
+//       <game id="12" tournament="3" round="1" black="1" white="2" bresult="0.5" opening="5" rule="1">
+//         <move>h8 h9 i9 g8 ...</move>
+//       </game>
+//       ...
+//
+// and likewise <countries>, <rules> and <openings>. Unknown elements and attributes are ignored.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use roxmltree::{Document, Node};
+use serde::Serialize;
+
+use crate::notation::*;
+use crate::types::*;
+
+const SIDE: i32 = 15;
+
+#[derive(Clone, Serialize)]
+pub struct RifPlayer {
+    pub name: String,
+    pub surname: String,
+    pub country: Option<u32>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RifTournament {
+    pub name: String,
+    pub year: Option<i32>,
+    pub rule: Option<u32>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RifGame {
+    pub id: u32,
+    pub tournament: Option<u32>,
+    pub round: String,
+    pub black: Option<u32>,
+    pub white: Option<u32>,
+    pub rule: Option<u32>,
+    pub opening: Option<u32>,
+    // 1 when Black won, 0.5 for a draw, 0 when White won
+    pub black_score: Option<f32>,
+    pub moves: Vec<Move>,
+    // How the moves end the game, a final black foul included; None when they stop before the end
+    pub end: Option<GameEnd>,
+}
+
+impl RifGame {
+    // Every position of the game as the moves leading to it, from the empty board to the last move
+    pub fn positions(&self) -> impl Iterator<Item = &[Move]> {
+        (0..self.moves.len() + 1).map(move |i| &self.moves[..i])
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct RifDatabase {
+    pub countries: HashMap<u32, String>,
+    pub players: HashMap<u32, RifPlayer>,
+    pub tournaments: HashMap<u32, RifTournament>,
+    pub rules: HashMap<u32, String>,
+    pub openings: HashMap<u32, String>,
+    pub games: Vec<RifGame>,
+    // Games left out because their moves do not replay, by id with the reason
+    pub rejected: Vec<(u32, String)>,
+}
+
+impl RifDatabase {
+    // Reads an archive, replaying every game under renju on `bd`, which is left cleared and 15x15 with its own rule
+    pub fn parse(xml: &str, bd: &mut Board) -> Result<RifDatabase, String> {
+        let doc: Document = Document::parse(xml).map_err(|e| e.to_string())?;
+        let root: Node = doc.root_element();
+
+        if !bd.set_side(SIDE) {
+            return Err("no 15x15 board".to_string());
+        }
+
+        let rule: Rule = bd.rule();
+        bd.set_rule(Rule::Renju);
+        let db: RifDatabase = Self::read(root, bd);
+        bd.set_rule(rule);
+
+        Ok(db)
+    }
+
+    fn read(root: Node, bd: &mut Board) -> RifDatabase {
+        let mut db: RifDatabase = RifDatabase {
+            countries: Self::table(root, "countries", "country", |n| attr(n, "name")),
+            players: Self::table(root, "players", "player", |n| RifPlayer {
+                name: attr(n, "name"),
+                surname: attr(n, "surname"),
+                country: number(n, "country"),
+            }),
+            tournaments: Self::table(root, "tournaments", "tournament", |n| RifTournament {
+                name: attr(n, "name"),
+                year: number(n, "year"),
+                rule: number(n, "rule"),
+            }),
+            rules: Self::table(root, "rules", "rule", |n| attr(n, "name")),
+            openings: Self::table(root, "openings", "opening", |n| attr(n, "name")),
+            games: Vec::new(),
+            rejected: Vec::new(),
+        };
+
+        for n in Self::elements(root, "games", "game") {
+            let id: u32 = number(n, "id").unwrap_or(0);
+
+            match Self::replay(n, bd) {
+                Ok((moves, end)) => db.games.push(RifGame {
+                    id,
+                    tournament: number(n, "tournament"),
+                    round: attr(n, "round"),
+                    black: number(n, "black"),
+                    white: number(n, "white"),
+                    rule: number(n, "rule"),
+                    opening: number(n, "opening"),
+                    black_score: number(n, "bresult"),
+                    moves,
+                    end,
+                }),
+                Err(e) => db.rejected.push((id, e)),
+            }
+        }
+        bd.reset();
+
+        db
+    }
+
+    pub fn games(&self) -> impl Iterator<Item = &RifGame> {
+        self.games.iter()
+    }
+
+    // Every position of every game, with the game it comes from
+    pub fn positions(&self) -> impl Iterator<Item = (&RifGame, &[Move])> {
+        self.games.iter().flat_map(|g| g.positions().map(move |p| (g, p)))
+    }
+
+    // "Name Surname" of a player
+    pub fn player_name(&self, id: u32) -> Option<String> {
+        self.players.get(&id).map(|p| format!("{} {}", p.name, p.surname).trim().to_string())
+    }
+
+    // The `item` children of the `list` elements under the root, e.g. games/game
+    fn elements<'a, 'input>(root: Node<'a, 'input>, list: &'static str, item: &'static str) -> impl Iterator<Item = Node<'a, 'input>> {
+        root.children().filter(move |n| n.has_tag_name(list))
+            .flat_map(|n| n.children())
+            .filter(move |n| n.has_tag_name(item))
+    }
+
+    // Elements of a lookup table such as <players>, by their id
+    fn table<T>(root: Node, list: &'static str, item: &'static str, f: impl Fn(Node) -> T) -> HashMap<u32, T> {
+        Self::elements(root, list, item)
+            .filter_map(|n| number(n, "id").map(|id| (id, f(n))))
+            .collect()
+    }
+
+    // White may leave a black foul unclaimed and play on, so a foul only ends the game as the last move
+    fn replay(game: Node, bd: &mut Board) -> Result<(Vec<Move>, Option<GameEnd>), String> {
+        let text: &str = game.children().find(|n| n.has_tag_name("move")).and_then(|n| n.text()).unwrap_or("");
+        let moves: Vec<Move> = parse_moves(text, SIDE)?;
+
+        bd.reset();
+        for (i, m) in moves.iter().enumerate() {
+            match bd.check_move(*m) {
+                Err(_) if Self::only_fouls(bd, *m) => {},
+                checked => checked.map_err(|e| format!("move {} {} is {}", i + 1, move_to_string(*m, SIDE), e))?,
+            }
+            bd.do_move(*m);
+        }
+
+        Ok((moves, bd.game_end()))
+    }
+
+    // Whether nothing but a black foul, this move or the one before, keeps `m` from being played
+    fn only_fouls(bd: &Board, m: Move) -> bool {
+        bd.game_end().is_none_or(|e| e.foul.is_some()) && (m == MOVE_PASS || (bd.contains(m) && bd.is_empty(m)))
+    }
+}
+
+fn attr(n: Node, name: &str) -> String {
+    n.attribute(name).unwrap_or("").to_string()
+}
+
+// Missing or empty attributes read as None
+fn number<T: FromStr>(n: Node, name: &str) -> Option<T> {
+    n.attribute(name)?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARCHIVE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<database>
+  <countries><country id="7" name="Estonia"/></countries>
+  <players>
+    <player id="1" name="Ants" surname="Soosõrv" country="7"/>
+    <player id="2" name="Ana" surname=""/>
+  </players>
+  <tournaments><tournament id="3" name="Open" year="2019" rule="1"/></tournaments>
+  <rules><rule id="1" name="RIF"/></rules>
+  <openings><opening id="5" name="Kagetsu"/></openings>
+  <games>
+    <game id="10" tournament="3" round="1" black="1" white="2" bresult="0.5" opening="5" rule="1"><move>h8 h9 i9</move></game>
+    <game id="11" black="1" white="2" bresult="1"><move>h8 a1 h9 b1 h10 c1 h11 d1 h12</move></game>
+    <game id="12" black="1" white="2" bresult="0"><move>h10 a1 h9 c1 f8 e1 g8 g1 h8</move></game>
+    <game id="13" black="1" white="2" bresult="1"><move>h10 a1 h9 c1 f8 e1 g8 g1 h8 o15 a15</move></game>
+    <game id="14"><move>h8 a1 h9 b1 h10 c1 h11 d1 h12 e1</move></game>
+    <game id="15"><move>h8 h8</move></game>
+    <game id="16"><move>h8 z1</move></game>
+    <game id="17"/>
+    <game id="18"><move>h8 h9</move></game>
+  </games>
+</database>"#;
+
+    fn parse() -> (RifDatabase, Board) {
+        let mut bd: Board = Board::new();
+        bd.set_rule(Rule::Freestyle);
+        let db: RifDatabase = RifDatabase::parse(ARCHIVE, &mut bd).unwrap();
+        (db, bd)
+    }
+
+    fn game(db: &RifDatabase, id: u32) -> &RifGame {
+        db.games().find(|g| g.id == id).unwrap()
+    }
+
+    #[test]
+    fn tables() {
+        let (db, _) = parse();
+
+        assert_eq!(db.countries.get(&7).map(|c| c.as_str()), Some("Estonia"));
+        assert_eq!(db.player_name(1), Some("Ants Soosõrv".to_string()));
+        assert_eq!(db.player_name(2), Some("Ana".to_string()));
+        assert_eq!(db.player_name(3), None);
+        assert_eq!(db.tournaments[&3].year, Some(2019));
+        assert_eq!(db.rules[&1], "RIF");
+        assert_eq!(db.openings[&5], "Kagetsu");
+
+        let g: &RifGame = game(&db, 10);
+        assert_eq!((g.tournament, g.round.as_str(), g.black, g.white, g.opening, g.rule), (Some(3), "1", Some(1), Some(2), Some(5), Some(1)));
+        assert_eq!(g.black_score, Some(0.5));
+        assert_eq!(g.positions().count(), 4);
+        assert!(g.end.is_none());
+    }
+
+    #[test]
+    fn games() {
+        let (db, bd) = parse();
+
+        assert_eq!(db.games().map(|g| g.id).collect::<Vec<u32>>(), vec![10, 11, 12, 13, 17, 18]);
+        assert_eq!(db.rejected.iter().map(|r| r.0).collect::<Vec<u32>>(), vec![14, 15, 16]);
+        assert_eq!(db.positions().count(), 4 + 10 + 10 + 12 + 1 + 3);
+
+        assert_eq!(game(&db, 11).end.as_ref().map(|e| e.result), Some(1));
+
+        // Black's last stone is a double three
+        let end: &GameEnd = game(&db, 12).end.as_ref().unwrap();
+        assert_eq!(end.result, 2);
+        assert_eq!(end.foul.as_ref().map(|f| f.point), Some([7, 7]));
+
+        // White played on instead of claiming it
+        assert!(game(&db, 13).end.is_none());
+
+        assert!(bd.rule() == Rule::Freestyle);
+        assert_eq!(bd.side(), SIDE);
+        assert_eq!(bd.piece_cnt, 0);
+    }
+
+    #[test]
+    fn not_xml() {
+        let mut bd: Board = Board::new();
+        assert!(RifDatabase::parse("<database>", &mut bd).is_err());
+    }
+}
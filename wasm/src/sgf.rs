@@ -0,0 +1,415 @@
+// Smart Game Format records for gomoku and renju (GM[4]), see https://www.red-bean.com/sgf/
+//
+//   (;GM[4]FF[4]SZ[15]PB[Black]PW[White];B[hh];W[hi]C[the usual reply](;B[ii])(;B[gi]))
+//
+// A square is its file and row from the top left, each a letter from 'a'. Only the first game of a
+// collection is read, and setup stones (AB, AW) are not supported. Moves keep the colour they are
+// recorded in, so a game may start with White or have one side play twice.
+
+use serde::{Deserialize, Serialize};
+
+use crate::notation::*;
+use crate::types::*;
+
+// Who plays a move, as its B or W property tells
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SgfColor {
+    Black,
+    White,
+}
+
+impl SgfColor {
+    fn other(&self) -> SgfColor {
+        match self {
+            SgfColor::Black => SgfColor::White,
+            SgfColor::White => SgfColor::Black,
+        }
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SgfNode {
+    // MOVE_NONE when the node plays no move, as the root does
+    pub m: Move,
+    // Who plays `m`; None takes turns with the move before, Black first
+    #[serde(default)]
+    pub color: Option<SgfColor>,
+    pub comment: String,
+    // Other properties in file order, such as ("PB", ["name"]) at the root
+    pub properties: Vec<(String, Vec<String>)>,
+    pub children: Vec<SgfNode>,
+}
+
+// Reads the first game of a collection, replaying every variation on `bd`, which is resized to the game
+pub fn read_sgf(s: &str, bd: &mut Board) -> Result<SgfNode, String> {
+    let mut parser: Parser = Parser { chars: s.chars().collect(), pos: 0 };
+    parser.skip_space();
+    let mut root: SgfNode = parser.tree()?;
+
+    if let Some(gm) = root.property("GM") {
+        if gm.trim() != "4" {
+            return Err(format!("GM[{}] is not a gomoku record", gm));
+        }
+    }
+    let side: i32 = match root.property("SZ") {
+        Some(sz) => sz.trim().parse().map_err(|_| format!("invalid board size SZ[{}]", sz))?,
+        None => BOARD_SIDE,
+    };
+    if !bd.set_side(side) {
+        return Err(format!("unsupported board size {}x{}", side, side));
+    }
+    if ["B", "W", "AB", "AW"].iter().any(|id| root.property(id).is_some()) {
+        return Err("stones in the root node are not supported".to_string());
+    }
+
+    replay(&mut root, bd, &mut Vec::new())?;
+
+    Ok(root)
+}
+
+// Writes `root` as a game on a `side` x `side` board; GM, FF and SZ are added to the root
+pub fn write_sgf(root: &SgfNode, side: i32) -> String {
+    let mut out: String = format!("(;GM[4]FF[4]SZ[{}]", side);
+
+    write_properties(&mut out, root, &["GM", "FF", "SZ"]);
+    write_children(&mut out, root, SgfColor::Black);
+    out += ")\n";
+
+    out
+}
+
+impl SgfNode {
+    // First value of a property
+    pub fn property(&self, id: &str) -> Option<&str> {
+        self.properties.iter().find(|(k, _)| k == id).and_then(|(_, v)| v.first()).map(|v| v.as_str())
+    }
+}
+
+// `color` is the colour to move after `node` when its children do not say
+fn write_children(out: &mut String, node: &SgfNode, color: SgfColor) {
+    let variations: bool = node.children.len() > 1;
+
+    for child in node.children.iter() {
+        let mut next: SgfColor = color;
+
+        if variations {
+            *out += "(";
+        }
+        *out += ";";
+        if child.m != MOVE_NONE {
+            let c: SgfColor = child.color.unwrap_or(color);
+            *out += if c == SgfColor::Black {"B"} else {"W"};
+            *out += &format!("[{}]", if child.m == MOVE_PASS {String::new()} else {square_to_string(child.m)});
+            next = c.other();
+        }
+        write_properties(out, child, &[]);
+        write_children(out, child, next);
+        if variations {
+            *out += ")";
+        }
+    }
+}
+
+fn write_properties(out: &mut String, node: &SgfNode, skip: &[&str]) {
+    if !node.comment.is_empty() {
+        *out += &format!("C[{}]", escape(&node.comment));
+    }
+    for (id, values) in node.properties.iter().filter(|(id, _)| !skip.contains(&id.as_str())) {
+        *out += id;
+        for v in values.iter() {
+            *out += &format!("[{}]", escape(v));
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace(']', "\\]")
+}
+
+fn square_to_string(m: Move) -> String {
+    format!("{}{}", (b'a' + _file_of(m) as u8) as char, (b'a' + _rank_of(m) as u8) as char)
+}
+
+// "hh" for the centre of the 15x15 board; "" is a pass, and so is "tt" where it is off the board
+fn square(s: &str, side: i32) -> Result<Move, String> {
+    let cs: Vec<char> = s.chars().collect();
+
+    match cs.as_slice() {
+        [] => Ok(MOVE_PASS),
+        ['t', 't'] if side <= 19 => Ok(MOVE_PASS),
+        [f, r] if f.is_ascii_lowercase() && r.is_ascii_lowercase() && (*f as i32 - 'a' as i32) < side && (*r as i32 - 'a' as i32) < side =>
+            Ok(_make_move(&(*r as i32 - 'a' as i32), &(*f as i32 - 'a' as i32))),
+        _ => Err(format!("invalid move [{}]", s)),
+    }
+}
+
+// Plays every child of `node` from the position on `bd`, `line` being the moves that led there,
+// and turns their B and W properties into moves. Two moves of one colour in a row, which FF[4] allows,
+// are played with a pass of the other colour in between.
+fn replay(node: &mut SgfNode, bd: &mut Board, line: &mut Vec<Move>) -> Result<(), String> {
+    let side: i32 = bd.side();
+    let at = |line: &[Move]| if line.is_empty() {"at the start".to_string()} else {format!("after \"{}\"", moves_to_string(line, side))};
+
+    for child in node.children.iter_mut() {
+
+        if child.property("AB").is_some() || child.property("AW").is_some() {
+            return Err(format!("setup stones {} are not supported", at(line)));
+        }
+
+        let mut moves: Vec<Move> = Vec::new();
+        if let Some(i) = child.properties.iter().position(|(id, _)| id == "B" || id == "W") {
+            let (id, values) = child.properties.remove(i);
+            child.m = square(&values[0], side)?;
+            child.color = Some(if id == "B" {SgfColor::Black} else {SgfColor::White});
+            if (id == "B") != (bd.side_to_move == Color::Black) {
+                moves.push(MOVE_PASS);
+            }
+        }
+        if child.m != MOVE_NONE {
+            moves.push(child.m);
+        }
+
+        let mut result: Result<(), String> = Ok(());
+        let mut played: usize = 0;
+        for m in moves.iter() {
+            if let Err(e) = bd.check_move(*m) {
+                result = Err(format!("{} {} is {}", move_to_string(*m, side), at(line), e));
+                break;
+            }
+            bd.do_move(*m);
+            line.push(*m);
+            played += 1;
+        }
+        if result.is_ok() {
+            result = replay(child, bd, line);
+        }
+        for _ in 0..played {
+            line.pop();
+            bd.undo_move();
+        }
+        result?;
+    }
+
+    Ok(())
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn skip_space(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_space();
+        if self.peek() != Some(c) {
+            return Err(format!("expected '{}' at character {}", c, self.pos));
+        }
+        self.pos += 1;
+
+        Ok(())
+    }
+
+    // "(" node+ tree* ")", the nodes chained as each other's only child
+    fn tree(&mut self) -> Result<SgfNode, String> {
+        self.expect('(')?;
+
+        let mut sequence: Vec<SgfNode> = Vec::new();
+        self.skip_space();
+        while self.peek() == Some(';') {
+            self.pos += 1;
+            sequence.push(self.node()?);
+            self.skip_space();
+        }
+        if sequence.is_empty() {
+            return Err(format!("empty game tree at character {}", self.pos));
+        }
+
+        let mut last: SgfNode = sequence.pop().unwrap_or_default();
+        while self.peek() == Some('(') {
+            last.children.push(self.tree()?);
+            self.skip_space();
+        }
+        self.expect(')')?;
+
+        while let Some(mut node) = sequence.pop() {
+            node.children.push(last);
+            last = node;
+        }
+
+        Ok(last)
+    }
+
+    fn node(&mut self) -> Result<SgfNode, String> {
+        let mut node: SgfNode = SgfNode::default();
+
+        loop {
+            self.skip_space();
+            let start: usize = self.pos;
+            while self.peek().is_some_and(|c| c.is_ascii_uppercase()) {
+                self.pos += 1;
+            }
+            if self.pos == start {
+                break;
+            }
+
+            let id: String = self.chars[start..self.pos].iter().collect();
+            let mut values: Vec<String> = Vec::new();
+            self.skip_space();
+            while self.peek() == Some('[') {
+                values.push(self.value()?);
+                self.skip_space();
+            }
+            if values.is_empty() {
+                return Err(format!("property {} without a value at character {}", id, start));
+            }
+
+            if id == "C" {
+                node.comment = values[0].clone();
+            } else {
+                node.properties.push((id, values));
+            }
+        }
+
+        Ok(node)
+    }
+
+    fn value(&mut self) -> Result<String, String> {
+        let start: usize = self.pos;
+        let mut s: String = String::new();
+        self.pos += 1;
+
+        loop {
+            match self.peek() {
+                None => return Err(format!("unterminated value at character {}", start)),
+                Some(']') => break,
+                Some('\\') => {
+                    self.pos += 1;
+                    // An escaped line break is a soft break and disappears
+                    match self.peek() {
+                        Some('\n') | Some('\r') => {},
+                        Some(c) => s.push(c),
+                        None => continue,
+                    }
+                },
+                Some(c) => s.push(c),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(s: &str) -> Result<SgfNode, String> {
+        let mut bd: Board = Board::new();
+        read_sgf(s, &mut bd)
+    }
+
+    fn moves(node: &SgfNode) -> Vec<Move> {
+        match node.children.first() {
+            Some(child) => std::iter::once(child.m).chain(moves(child)).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn variations_round_trip() {
+        let record: &str = "(;GM[4]FF[4]SZ[15]PB[Black]PW[White];B[hh];W[hi]C[the usual reply](;B[ii])(;B[gi];W[gg]))\n";
+        let root: SgfNode = read(record).unwrap();
+
+        assert_eq!(root.property("PB"), Some("Black"));
+        let hi: &SgfNode = &root.children[0].children[0];
+        assert_eq!((hi.m, hi.color), (_make_move(&8, &7), Some(SgfColor::White)));
+        assert_eq!(hi.comment, "the usual reply");
+        assert_eq!(hi.children.iter().map(|c| c.m).collect::<Vec<Move>>(), vec![_make_move(&8, &8), _make_move(&8, &6)]);
+        assert_eq!(hi.children[1].children[0].color, Some(SgfColor::White));
+
+        assert_eq!(write_sgf(&root, 15), record);
+        assert_eq!(read(&write_sgf(&root, 15)), Ok(root));
+    }
+
+    #[test]
+    fn escapes_and_soft_line_breaks() {
+        let root: SgfNode = read("(;C[a \\] b \\\\ c];B[hh]C[one\\\ntwo]GN[x\\:y])").unwrap();
+
+        assert_eq!(root.comment, "a ] b \\ c");
+        assert_eq!(root.children[0].comment, "onetwo");
+        assert_eq!(root.children[0].property("GN"), Some("x:y"));
+
+        let written: String = write_sgf(&root, 15);
+        assert_eq!(written, "(;GM[4]FF[4]SZ[15]C[a \\] b \\\\ c];B[hh]C[onetwo]GN[x:y])\n");
+        assert_eq!(read(&written).map(|root| write_sgf(&root, 15)), Ok(written.clone()));
+    }
+
+    #[test]
+    fn passes() {
+        let root: SgfNode = read("(;SZ[15];B[hh];W[];B[ii];W[tt])").unwrap();
+        assert_eq!(moves(&root), vec![_make_move(&7, &7), MOVE_PASS, _make_move(&8, &8), MOVE_PASS]);
+        assert_eq!(write_sgf(&root, 15), "(;GM[4]FF[4]SZ[15];B[hh];W[];B[ii];W[])\n");
+
+        // "tt" is a square on the 20x20 board
+        let root: SgfNode = read("(;SZ[20];B[tt])").unwrap();
+        assert_eq!(moves(&root), vec![_make_move(&19, &19)]);
+    }
+
+    #[test]
+    fn colours_as_stored() {
+        let node = |m: Move, color: Option<SgfColor>, children: Vec<SgfNode>| SgfNode { m, color, children, ..SgfNode::default() };
+        let hh: Move = _make_move(&7, &7);
+        let ii: Move = _make_move(&8, &8);
+
+        let alternating: SgfNode = node(MOVE_NONE, None, vec![node(hh, None, vec![node(ii, None, vec![])])]);
+        assert_eq!(write_sgf(&alternating, 15), "(;GM[4]FF[4]SZ[15];B[hh];W[ii])\n");
+
+        // White first, as after a swap recorded elsewhere
+        let white: SgfNode = node(MOVE_NONE, None, vec![node(hh, Some(SgfColor::White), vec![node(ii, None, vec![])])]);
+        let written: String = write_sgf(&white, 15);
+        assert_eq!(written, "(;GM[4]FF[4]SZ[15];W[hh];B[ii])\n");
+
+        // Read back, every move carries the colour it was written in
+        let stored: SgfNode = node(MOVE_NONE, None, vec![node(hh, Some(SgfColor::White), vec![node(ii, Some(SgfColor::Black), vec![])])]);
+        let root: SgfNode = read(&written).unwrap();
+        assert_eq!(root.children, stored.children);
+        assert_eq!(write_sgf(&root, 15), written);
+
+        // Black twice in a row
+        let twice: SgfNode = node(MOVE_NONE, None, vec![node(hh, None, vec![node(ii, Some(SgfColor::Black), vec![])])]);
+        let written: String = write_sgf(&twice, 15);
+        assert_eq!(written, "(;GM[4]FF[4]SZ[15];B[hh];B[ii])\n");
+        assert_eq!(moves(&read(&written).unwrap()), vec![hh, ii]);
+    }
+
+    #[test]
+    fn bad_records() {
+        for s in [
+            "",
+            "(;GM[1])",
+            "(;SZ[3])",
+            "(;AB[hh])",
+            "(;B[hh];AW[ii])",
+            "(;B[zz])",
+            "(;B[hh];W[hh])",
+            "(;C[open",
+            "(;B)",
+            "(;B[hh]",
+        ] {
+            assert!(read(s).is_err(), "{:?}", s);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use serde::Serialize;
+
+use crate::types::*;
+
+// Search limits for judging how balanced a position is
+pub const JUDGE_DEPTH: Depth = 4;
+pub const JUDGE_NODES: u64 = 20000;
+// Moves of each colour tried when placing the two extra Swap2 stones
+pub const PAIR_CANDIDATES: usize = 6;
+// Scores within this margin are too close to call
+pub const BALANCE_MARGIN: Score = 60;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapChoice {
+    // Take Black, the opponent plays the next (white) move
+    Black,
+    // Keep White and play the move in `moves`
+    White,
+    // Place the stones in `moves` and let the opponent choose
+    Place,
+}
+
+// What `swap_move` reports back to the caller
+#[derive(Clone, Serialize)]
+pub struct SwapResult {
+    pub choice: SwapChoice,
+    // Stones to put on the board as [rank, file] pairs, in move order
+    pub moves: Vec<[i32; 2]>,
+    // Judged score for White to move after the placed stones, or for our colour after the choice
+    pub score: Score,
+}
+
+impl SwapResult {
+    pub fn new(choice: SwapChoice, moves: &[Move], score: Score) -> SwapResult {
+        Self {
+            choice,
+            moves: moves.iter().map(|m| [_rank_of(*m), _file_of(*m)]).collect(),
+            score,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Engine;
+
+    #[test]
+    fn results_use_rank_and_file() {
+        let result: SwapResult = SwapResult::new(SwapChoice::Place, &[_make_move(&7, &7), _make_move(&0, &14)], -5);
+
+        assert_eq!(result.choice, SwapChoice::Place);
+        assert_eq!(result.moves, vec![[7, 7], [0, 14]]);
+        assert_eq!(result.score, -5);
+    }
+
+    #[test]
+    fn only_swap_points_are_answered() {
+        let mut engine: Engine = Engine::new();
+        let moves: Vec<Move> = [(7, 7), (6, 7), (8, 8), (6, 8), (5, 5)].iter().map(|(r, f)| _make_move(r, f)).collect();
+
+        for n in [1, 2, 4] {
+            assert!(engine._swap_move(&moves[..n], false).is_none());
+            assert!(engine._swap_move(&moves[..n], true).is_none());
+        }
+        assert!(engine._swap_move(&moves, false).is_none());
+    }
+
+    #[test]
+    fn swap1_picks_a_colour() {
+        let mut engine: Engine = Engine::new();
+        let moves: Vec<Move> = [(7, 7), (6, 7), (8, 8)].iter().map(|(r, f)| _make_move(r, f)).collect();
+        let result: SwapResult = engine._swap_move(&moves, false).unwrap();
+
+        match result.choice {
+            SwapChoice::Black => assert!(result.moves.is_empty()),
+            SwapChoice::White => {
+                assert_eq!(result.moves.len(), 1);
+                assert!(!moves.contains(&_make_move(&result.moves[0][0], &result.moves[0][1])));
+            },
+            SwapChoice::Place => panic!("Swap1 never places stones"),
+        }
+    }
+
+    #[test]
+    fn empty_board_gets_three_stones() {
+        let mut engine: Engine = Engine::new();
+        let result: SwapResult = engine._swap_move(&[], true).unwrap();
+
+        assert_eq!(result.choice, SwapChoice::Place);
+        assert_eq!(result.moves.len(), 3);
+        assert_eq!(result.moves[0], [7, 7]);
+        for (i, m) in result.moves.iter().enumerate() {
+            assert!((5..10).contains(&m[0]) && (5..10).contains(&m[1]));
+            assert!(!result.moves[..i].contains(m));
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::cmp;
+
+use crate::types::*;
+
+// Moves we still expect to play when the time control does not say
+const MOVES_HORIZON: i32 = 30;
+const MOVES_HORIZON_MIN: i32 = 10;
+const MIN_THINKING_TIME: i32 = 100;
+const SCORE_DROP_MARGIN: Score = 30;
+
+// Game clock as seen by the side to move, all in milliseconds
+#[derive(Clone, Copy, Default)]
+pub struct TimeControl {
+    pub time_left: i32,
+    pub increment: i32,
+    // 0 when the whole game has to be played with `time_left`
+    pub moves_to_go: i32,
+    // Upper bound for a single move, 0 for none
+    pub move_time: i32,
+}
+
+pub struct TimeManager {
+    soft: f64,
+    hard: i32,
+    stable_iterations: i32,
+    last_best: Move,
+    last_score: Score,
+}
+
+impl TimeManager {
+    pub fn new(tc: &TimeControl, piece_cnt: usize) -> TimeManager {
+        let mtg: i32 = if tc.moves_to_go > 0 {
+            tc.moves_to_go
+        } else {
+            cmp::max(MOVES_HORIZON - piece_cnt as i32 / 4, MOVES_HORIZON_MIN)
+        };
+
+        let available: i32 = cmp::max(tc.time_left.saturating_add(tc.increment.saturating_mul(mtg - 1)), 0);
+        let mut soft: i32 = available / mtg;
+        // Never bet more than a third of the clock on one move
+        let mut hard: i32 = cmp::min(soft.saturating_mul(4), (tc.time_left / 3).saturating_add(tc.increment));
+
+        if tc.move_time > 0 {
+            hard = cmp::min(hard, tc.move_time);
+        }
+
+        // Think a little even on a short budget, but never past the flag
+        hard = cmp::min(cmp::max(hard, MIN_THINKING_TIME), cmp::max(tc.time_left, 0));
+        soft = cmp::min(cmp::max(soft, MIN_THINKING_TIME), hard);
+
+        Self {
+            soft: soft as f64,
+            hard,
+            stable_iterations: 0,
+            last_best: MOVE_NONE,
+            last_score: SCORE_NONE,
+        }
+    }
+
+    pub fn hard_limit(&self) -> i32 {
+        self.hard
+    }
+
+    // Called after every completed iteration; returns true when another one is not worth starting
+    pub fn stop_early(&mut self, rem: &RootExtMove, elapsed: f64) -> bool {
+        let mut factor: f64 = 1.0;
+
+        if rem.pv[0] == self.last_best {
+            self.stable_iterations += 1;
+        } else {
+            self.stable_iterations = 0;
+        }
+
+        if self.last_best != MOVE_NONE && self.stable_iterations == 0 {
+            // The best move just changed, give it time to settle
+            factor *= 1.6;
+        } else if self.stable_iterations >= 4 {
+            factor *= 0.5;
+        } else if self.stable_iterations >= 2 {
+            factor *= 0.75;
+        }
+
+        if self.last_score != SCORE_NONE && rem.score < self.last_score - SCORE_DROP_MARGIN {
+            factor *= 1.5;
+        }
+
+        self.last_best = rem.pv[0];
+        self.last_score = rem.score;
+
+        elapsed >= self.soft * factor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn iteration(m: Move, score: Score) -> RootExtMove {
+        let mut rem: RootExtMove = RootExtMove::new();
+        rem.score = score;
+        rem.pv[0] = m;
+        rem
+    }
+
+    fn manager(time_left: i32, increment: i32, moves_to_go: i32, move_time: i32) -> TimeManager {
+        TimeManager::new(&TimeControl { time_left, increment, moves_to_go, move_time }, 0)
+    }
+
+    #[test]
+    fn budgets() {
+        // A thirtieth of a minute, at most four times that
+        let tm: TimeManager = manager(60000, 0, 0, 0);
+        assert_eq!((tm.soft, tm.hard_limit()), (2000.0, 8000));
+
+        // Ten moves to go with the increments to come, capped by a third of the clock
+        let tm: TimeManager = manager(10000, 1000, 10, 0);
+        assert_eq!((tm.soft, tm.hard_limit()), (1900.0, 4333));
+
+        assert_eq!(manager(60000, 0, 0, 500).hard_limit(), 500);
+        assert_eq!(manager(50, 0, 0, 0).hard_limit(), 50);
+        assert_eq!(manager(-20, 0, 0, 0).hard_limit(), 0);
+
+        // Fewer moves are expected later in the game
+        let late: TimeManager = TimeManager::new(&TimeControl { time_left: 60000, ..TimeControl::default() }, 100);
+        assert_eq!(late.soft, 6000.0);
+    }
+
+    #[test]
+    fn stable_best_moves_stop_early() {
+        let mut tm: TimeManager = manager(60000, 0, 0, 0);
+        let m: Move = _make_move(&7, &7);
+
+        assert!(!tm.stop_early(&iteration(m, 0), 1500.0));
+        assert!(!tm.stop_early(&iteration(m, 0), 1500.0));
+        assert!(tm.stop_early(&iteration(m, 0), 1500.0));
+    }
+
+    #[test]
+    fn changes_and_drops_take_longer() {
+        let mut tm: TimeManager = manager(60000, 0, 0, 0);
+        assert!(!tm.stop_early(&iteration(_make_move(&7, &7), 0), 1000.0));
+        assert!(!tm.stop_early(&iteration(_make_move(&7, &8), 0), 2500.0));
+        assert!(tm.stop_early(&iteration(_make_move(&7, &8), 0), 3200.0));
+
+        let mut tm: TimeManager = manager(60000, 0, 0, 0);
+        assert!(!tm.stop_early(&iteration(_make_move(&7, &7), 0), 1000.0));
+        assert!(!tm.stop_early(&iteration(_make_move(&7, &7), -100), 2500.0));
+        assert!(tm.stop_early(&iteration(_make_move(&7, &7), -100), 2500.0));
+    }
+}
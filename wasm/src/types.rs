@@ -1,14 +1,18 @@
-use strum_macros::{EnumCount as EnumCountMacro, EnumIter};
+use strum_macros::{EnumCount as EnumCountMacro, EnumIter, EnumString};
 use strum::IntoEnumIterator;
 use std::ops::{Add, Sub, Mul, Div, Index, IndexMut};
 use std::mem;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
 
 #[path="pattern.rs"]
 mod pattern;
 use pattern::*;
 
 pub const MOVE_NONE: Move = 0;
-pub const BOARD_SIZE: i32 = 15;
+// Off the board, so it never collides with a stone
+pub const MOVE_PASS: Move = 1;
+pub const BOARD_SIZE: i32 = BOARD_SIDE_MAX;
 pub const DEPTH_MAX: Depth = 120;
 pub const DEPTH_NUM: i32 = DEPTH_MAX as i32 + 1;
 pub const SCORE_NONE: Score = 32002;
@@ -30,14 +34,19 @@ pub const F1_SEE_SCORE: Score = 16;
 pub const B1_SEE_SCORE: Score = 1;
 pub const BOARD_BOUNDARY: i32 = 4;
 pub const BOARD_SIDE_BIT: i32 = 5;
+// Side of a new board; any side from BOARD_SIDE_MIN to BOARD_SIDE_MAX can be chosen per game
 pub const BOARD_SIDE: i32 = 15;
+pub const BOARD_SIDE_MIN: i32 = 5;
+pub const BOARD_SIDE_MAX: i32 = 20;
 pub const BOARD_SIDE_CAPACITY: i32 = 1 << BOARD_SIDE_BIT;
-pub const MOVE_SIZE: i32 = BOARD_SIDE.pow(2);
+pub const MOVE_SIZE: i32 = BOARD_SIDE_MAX.pow(2);
 pub const MOVE_CAPACITY: i32 = BOARD_SIDE_CAPACITY.pow(2);
-pub const STACK_SIZE: i32 = BOARD_SIDE.pow(2) + 1;
+pub const STACK_SIZE: i32 = BOARD_SIDE_MAX.pow(2) + 1;
 pub const WIN_SCORE_THRESHOLD: Score = WIN_SCORE - STACK_SIZE as i16;
 pub const BITBOARD_SIZE: i32 = MOVE_CAPACITY / 64 + 1;
-pub const VECTOR_SIZE: i32 = BOARD_SIDE * 6 - 2;
+pub const VECTOR_SIZE: i32 = BOARD_SIDE_MAX * 6 - 2;
+// Longest line the pattern tables cover
+const PATTERN_LINE_MAX: i32 = 15;
 pub const BONUS_F3D: Score = 1024;
 pub const BONUS_REFUTATION: Score = 1536;
 pub const SEE_THRESHOLD: Score = 12;
@@ -45,42 +54,45 @@ pub const MATERIAL_NONE: u32 = 15;
 pub const DEPTH_ITERATIVE_MAX: Depth = 100;
 pub const DEPTH_NONE: Depth = 127;
 pub const DEPTH_ITERATIVE_MIN: Depth = 1;
+pub const TIME_INFINITE: i32 = i32::MAX;
+// Milliseconds kept back from every time limit to return the move in time
+pub const MOVE_OVERHEAD: i32 = 50;
 
 pub type ZobristKey = u64;
 pub type Move = i16;
 pub type Score = i16;
 pub type Depth = i8;
 
-pub static mut TT: TranspositionTable = TranspositionTable::new();
-pub static mut BOARD: Option<Board> = None;
-pub static mut PLY: Depth = 0;
-pub static mut PLY_MAX: Depth = 0;
-pub static mut SEARCH_STACK: SearchStack = [SearchStackElement::new(); STACK_SIZE as usize];
-pub static mut ROOT_BESTS: Vec<RootExtMove> = Vec::new();
-pub static mut COUNTER_MOVES: CounterMoveHistory = [MOVE_NONE; MOVE_CAPACITY as usize];
-
 fn mul_hi64(a: u64, b: u64) -> u64 {
     (((a as u128) * (b as u128)) >> 64) as u64
 }
 
 fn get_bits(a: u32, begin: u32, end: u32) -> u32 {
-    (a >> begin) & (((1 as u32) << (end - begin)) - 1)
+    (a >> begin) & ((1u32 << (end - begin)) - 1)
 }
 
 fn set_bit(a: &mut u32, ind: i32) {
-    *a |= (1 as u32) << ind;
+    *a |= 1u32 << ind;
 }
 
 fn reset_bit(a: &mut u32, ind: i32) {
-    *a &= !((1 as u32) << ind);
+    *a &= !(1u32 << ind);
 }
 
 fn is_ok_move(m: Move) -> bool {
-    return 0 <= _rank_of(m) && _rank_of(m) < BOARD_SIDE && 0 <= _file_of(m) && _file_of(m) < BOARD_SIDE;
+    is_ok_move_on(m, BOARD_SIDE_MAX)
+}
+
+fn is_ok_move_on(m: Move, side: i32) -> bool {
+    0 <= _rank_of(m) && _rank_of(m) < side && 0 <= _file_of(m) && _file_of(m) < side
 }
 
-fn is_ok_score(s: Score) -> bool {
-    return -INFINITY_SCORE < s && s < INFINITY_SCORE;
+pub fn is_ok_side(side: i32) -> bool {
+    (BOARD_SIDE_MIN..=BOARD_SIDE_MAX).contains(&side)
+}
+
+pub fn is_ok_score(s: Score) -> bool {
+    -INFINITY_SCORE < s && s < INFINITY_SCORE
 }
 
 fn oppo_direction(d: Direction) -> Direction {
@@ -127,21 +139,21 @@ pub struct TTEntry {
     depth: Depth,
 }
 
-fn index_of_helper(m: Move, d: Direction) -> i32 {
+fn index_of_helper(m: Move, d: Direction, side: i32) -> i32 {
     match d {
         Direction::DRank => _rank_of(m),
-        Direction::DFile => _file_of(m) + BOARD_SIDE,
-        Direction::DMDiag => _mdiag_of(m) + BOARD_SIDE * 2,
-        Direction::DADiag => _adiag_of(m) + BOARD_SIDE * 4 - 1,
+        Direction::DFile => _file_of(m) + side,
+        Direction::DMDiag => _mdiag_of(m, side) + side * 2,
+        Direction::DADiag => _adiag_of(m, side) + side * 4 - 1,
     }
 }
 
-fn index_on_helper(m: Move, d: Direction) -> i32 {
+fn index_on_helper(m: Move, d: Direction, side: i32) -> i32 {
     match d {
         Direction::DRank => _file_of(m),
         Direction::DFile => _rank_of(m),
-        Direction::DMDiag => _mdiag_index_on(m),
-        Direction::DADiag => _adiag_index_on(m),
+        Direction::DMDiag => _mdiag_index_on(m, side),
+        Direction::DADiag => _adiag_index_on(m, side),
     }
 }
 
@@ -164,10 +176,6 @@ impl TTEntry {
         self.score
     }
 
-    fn is_pv(&self) -> bool {
-        (self.bound & 0x4) != 0
-    }
-
     pub fn bound(&self) -> Bound {
         match self.bound & 0x3 {
             3 => Bound::BoundExact,
@@ -182,24 +190,28 @@ impl TTEntry {
         self.depth
     }
 
-    fn save(&mut self, k: ZobristKey, m: Move, s: Score, b: Bound, pv: bool, d: Depth) {
+    fn relative_age(&self, generation8: u8) -> i32 {
+        (TranspositionTable::GENERATION_CYCLE + generation8 as i32 - self.bound as i32) & TranspositionTable::GENERATION_MASK
+    }
+
+    // One argument per entry field, as stored
+    #[allow(clippy::too_many_arguments)]
+    fn save(&mut self, k: ZobristKey, m: Move, s: Score, b: Bound, pv: bool, d: Depth, generation8: u8) {
         // Preserve any existing move for the same position
         if m != MOVE_NONE || k as u32 != self.key {
             self.move16 = m;
         }
 
         // Overwrite less valuable entries
-        if b == Bound::BoundExact || k as u32 != self.key || d > self.depth - 4 {
+        if b == Bound::BoundExact || k as u32 != self.key || d > self.depth - 4 || self.relative_age(generation8) != 0 {
             self.key     = k as u32;
             self.score   = s as Score;
-            unsafe {
-                self.bound   = TT.generation8 | (pv as u8) << 2| match b {
-                    Bound::BoundExact => 3,
-                    Bound::BoundLower => 2,
-                    Bound::BoundUpper => 1,
-                    Bound::BoundNone => 0
-                };
-            }
+            self.bound   = generation8 | (pv as u8) << 2| match b {
+                Bound::BoundExact => 3,
+                Bound::BoundLower => 2,
+                Bound::BoundUpper => 1,
+                Bound::BoundNone => 0
+            };
             self.depth   = d;
         }
     }
@@ -208,6 +220,8 @@ impl TTEntry {
 #[derive(Default, Clone, Copy)]
 struct Cluster {
     entry: [TTEntry; TranspositionTable::CLUSTER_SIZE],
+    // Keeps the cluster size as the table is laid out for
+    #[allow(dead_code)]
     padding: [char; 2]
 }
 
@@ -221,55 +235,118 @@ impl Cluster {
 }
 
 pub struct TranspositionTable {
+    hash_mb: usize,
     cluster_count: i32,
-    table: [Cluster; 256 * 1024 * 32],
+    table: Vec<Cluster>,
     generation8: u8,
 }
 
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TranspositionTable {
     const CLUSTER_SIZE: usize = 3;
-    const GENERATION_BITS: u32  = 3;
     const GENERATION_DELTA: u8 = (1 << 3);
     const GENERATION_CYCLE: i32 = 255 + (1 << 3);
     const GENERATION_MASK: i32  = (0xFF << 3) & 0xFF;
     const CLUSTER_SIZEOF: i32 = mem::size_of::<Cluster>() as i32;
 
-    const fn new() -> TranspositionTable {
+    // Kept small so that the page also loads on mobile browsers
+    pub const DEFAULT_HASH_MB: usize = 16;
+    // Keeps the size in bytes within a 32-bit usize, as on wasm32
+    pub const MAX_HASH_MB: usize = 2048;
+
+    pub const fn new() -> TranspositionTable {
         Self {
-            cluster_count: 256 * 1024 * 32,
-            table: [Cluster::new(); 256 * 1024 * 32],
+            hash_mb: Self::DEFAULT_HASH_MB,
+            cluster_count: 0,
+            table: Vec::new(),
             generation8: 0
         }
     }
 
-    fn new_search(&mut self) {
-        self.generation8 += Self::GENERATION_DELTA;
+    pub fn hash_mb(&self) -> usize {
+        self.hash_mb
+    }
+
+    // The table is allocated again at the next search; sizes are clamped to 1..=MAX_HASH_MB
+    pub fn set_hash_mb(&mut self, mb: usize) {
+        self.hash_mb = mb.clamp(1, Self::MAX_HASH_MB);
+        self.release();
     }
 
-    fn probe(&mut self, key: &ZobristKey, found: &mut bool) -> &mut TTEntry {
+    pub fn release(&mut self) {
+        self.cluster_count = 0;
+        self.table = Vec::new();
+        self.generation8 = 0;
+    }
+
+    // Halves the requested size until the allocation succeeds
+    fn allocate(&mut self) {
+        let mut cluster_count: usize = cmp::max(self.hash_mb * 1024 * 1024 / Self::CLUSTER_SIZEOF as usize, 1);
+
+        while cluster_count > 1 && self.table.try_reserve_exact(cluster_count).is_err() {
+            cluster_count /= 2;
+        }
+
+        self.table.resize(cluster_count, Cluster::new());
+        self.cluster_count = cluster_count as i32;
+    }
+
+    pub fn clear(&mut self) {
+        for cluster in self.table.iter_mut() {
+            *cluster = Cluster::new();
+        }
+        self.generation8 = 0;
+    }
+
+    pub fn new_search(&mut self) {
+        if self.cluster_count == 0 {
+            self.allocate();
+        }
+        self.generation8 = self.generation8.wrapping_add(Self::GENERATION_DELTA);
+    }
+
+    // Returns the index of the entry for `key`, or of the entry to be replaced when not found
+    pub fn probe(&mut self, key: &ZobristKey, found: &mut bool) -> usize {
         let key32: u32 = *key as u32;
+        let c: usize = mul_hi64(*key, self.cluster_count as u64) as usize;
+
         for i in 0..Self::CLUSTER_SIZE {
-            if self.table[mul_hi64(*key, self.cluster_count as u64) as usize].entry[i].key == key32 || !self.table[mul_hi64(*key, self.cluster_count as u64) as usize].entry[i].depth != 0 {
-                self.table[mul_hi64(*key, self.cluster_count as u64) as usize].entry[i].bound = self.generation8 | (self.table[mul_hi64(*key, self.cluster_count as u64) as usize].entry[i].bound & (Self::GENERATION_DELTA - 1)); // Refresh
+            let tte: &mut TTEntry = &mut self.table[c].entry[i];
+            if tte.key == key32 || tte.depth == 0 {
+                tte.bound = self.generation8 | (tte.bound & (Self::GENERATION_DELTA - 1)); // Refresh
 
-                *found = self.table[mul_hi64(*key, self.cluster_count as u64) as usize].entry[i].depth != 0;
+                *found = tte.depth != 0;
 
-                return &mut self.table[mul_hi64(*key, self.cluster_count as u64) as usize].entry[i];
+                return c * Self::CLUSTER_SIZE + i;
             }
         }
         // Find an entry to be replaced according to the replacement strategy
         let mut replace = 0;
         for i in 1..Self::CLUSTER_SIZE {
-            if self.table[mul_hi64(*key, self.cluster_count as u64) as usize].entry[replace].depth as i32 - ((Self::GENERATION_CYCLE + self.generation8 as i32 - self.table[mul_hi64(*key, self.cluster_count as u64) as usize].entry[replace].bound as i32) & Self::GENERATION_MASK) > self.table[mul_hi64(*key, self.cluster_count as u64) as usize].entry[i].depth as i32 - ((Self::GENERATION_CYCLE + self.generation8 as i32 - self.table[mul_hi64(*key, self.cluster_count as u64) as usize].entry[i].bound as i32) & Self::GENERATION_MASK) {
+            let r: &TTEntry = &self.table[c].entry[replace];
+            let e: &TTEntry = &self.table[c].entry[i];
+            if r.depth as i32 - r.relative_age(self.generation8) > e.depth as i32 - e.relative_age(self.generation8) {
                 replace = i;
             }
         }
         *found = false;
-        return &mut self.table[mul_hi64(*key, self.cluster_count as u64) as usize].entry[replace];
+        c * Self::CLUSTER_SIZE + replace
+    }
+
+    pub fn entry(&self, index: usize) -> TTEntry {
+        self.table[index / Self::CLUSTER_SIZE].entry[index % Self::CLUSTER_SIZE]
     }
 
-    fn first_entry(self, key: ZobristKey) -> TTEntry {
-        self.table[mul_hi64(key, self.cluster_count as u64) as usize].entry[0]
+    // Same fields as `TTEntry::save`, the generation is filled in here
+    #[allow(clippy::too_many_arguments)]
+    pub fn save(&mut self, index: usize, k: ZobristKey, m: Move, s: Score, b: Bound, pv: bool, d: Depth) {
+        let generation8: u8 = self.generation8;
+        self.table[index / Self::CLUSTER_SIZE].entry[index % Self::CLUSTER_SIZE].save(k, m, s, b, pv, d, generation8);
     }
 }
 
@@ -323,8 +400,14 @@ pub struct SearchStackElement {
     pub killers: [Move; 2],
 }
 
+impl Default for SearchStackElement {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SearchStackElement {
-    const fn new() -> SearchStackElement {
+    pub const fn new() -> SearchStackElement {
         Self {
             pv: None,
             killers: [0; 2]
@@ -447,6 +530,12 @@ pub struct RootExtMove {
     pub pv: Pv,
 }
 
+impl Default for RootExtMove {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RootExtMove {
     pub fn new() -> RootExtMove {
         Self {
@@ -465,6 +554,87 @@ impl RootExtMove {
     }
 }
 
+// What `think_and_move` reports back to the caller
+#[derive(Clone, Serialize)]
+pub struct SearchResult {
+    pub best_move: Move,
+    pub score: Score,
+    // Moves of the side to move until the game is decided: positive wins, negative loses
+    pub win_in: Option<i32>,
+    pub depth: Depth,
+    pub seldepth: Depth,
+    // Principal variation as [rank, file] pairs
+    pub pv: Vec<[i32; 2]>,
+    pub nodes: u64,
+    pub elapsed: f64,
+}
+
+impl SearchResult {
+    pub fn new(rem: &RootExtMove, seldepth: Depth, nodes: u64, elapsed: f64) -> SearchResult {
+        let plies: i32 = (WIN_SCORE - rem.score.abs()) as i32;
+
+        Self {
+            best_move: rem.pv[0],
+            score: rem.score,
+            win_in: if rem.score > WIN_SCORE_THRESHOLD {Some((plies + 1) / 2)} else if rem.score < -WIN_SCORE_THRESHOLD {Some(-plies / 2)} else {None},
+            depth: rem.depth,
+            seldepth,
+            pv: rem.pv.iter().take_while(|m| **m != MOVE_NONE).map(|m| [_rank_of(*m), _file_of(*m)]).collect(),
+            nodes,
+            elapsed,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FoulReason {
+    DoubleThree,
+    DoubleFour,
+    Overline,
+}
+
+#[derive(Clone, Serialize)]
+pub struct FoulLine {
+    // [rank, file] step along the line
+    pub direction: [i32; 2],
+    // Black stones that form the three, four or overline together with the foul point
+    pub stones: Vec<[i32; 2]>,
+}
+
+// What `fouls` reports for every forbidden point
+#[derive(Clone, Serialize)]
+pub struct Foul {
+    pub point: [i32; 2],
+    pub reason: FoulReason,
+    pub lines: Vec<FoulLine>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DrawReason {
+    BoardFull,
+    BothPassed,
+}
+
+// The stones that won, in order along `direction`
+#[derive(Clone, Serialize)]
+pub struct Five {
+    pub direction: [i32; 2],
+    pub stones: Vec<[i32; 2]>,
+}
+
+// What `game_end` reports once the game is decided
+#[derive(Clone, Serialize)]
+pub struct GameEnd {
+    // 1 Black wins, 2 White wins, 3 draw, as `check_wld_already` returns
+    pub result: i32,
+    pub five: Option<Five>,
+    // The last black stone, when it lost the game by breaking the renju rules
+    pub foul: Option<Foul>,
+    pub draw: Option<DrawReason>,
+}
+
 pub type CounterMoveHistory = [Move; MOVE_CAPACITY as usize];
 
 #[derive(Clone, Copy)]
@@ -559,15 +729,15 @@ pub struct BitBoard {
 
 impl BitBoard {
     fn contains(&self, m: Move) -> bool {
-        (self.bitboard[(m >> 6) as usize] & ((1 as u64) << (m & 63))) != 0
+        (self.bitboard[(m >> 6) as usize] & (1u64 << (m & 63))) != 0
     }
 
     fn insert(&mut self, m: Move) {
-        self.bitboard[(m >> 6) as usize] |= (1 as u64) << (m & 63);
+        self.bitboard[(m >> 6) as usize] |= 1u64 << (m & 63);
     }
 
     fn remove(&mut self, m: Move) {
-        self.bitboard[(m >> 6) as usize] &= !((1 as u64) << (m & 63));
+        self.bitboard[(m >> 6) as usize] &= !(1u64 << (m & 63));
     }
 
     fn reset(&mut self) {
@@ -587,7 +757,7 @@ pub struct MoveList<T: Copy> {
 impl<T: Copy> MoveList<T> {
     fn begin(&self, add: Option<usize>) -> T {
         match add {
-            Some(a) => self._movelist[0 + a],
+            Some(a) => self._movelist[a],
             None => self._movelist[0]
         }
     }
@@ -600,7 +770,7 @@ impl<T: Copy> MoveList<T> {
     }
 
     fn size(&self) -> i32 {
-        return self.off_the_end as i32;
+        self.off_the_end as i32
     }
 
     fn reset(&mut self) {
@@ -609,13 +779,11 @@ impl<T: Copy> MoveList<T> {
     }
 
     fn contains(&self, m: Move) -> bool {
-        return self.bitboard.contains(m);
+        self.bitboard.contains(m)
     }
 
     fn swap(&mut self, it1: usize, it2: usize) {
-        let tmp: T = self._movelist[it1];
-        self._movelist[it1] = self._movelist[it2];
-        self._movelist[it2] = tmp;
+        self._movelist.swap(it1, it2);
     }
 }
 
@@ -675,28 +843,14 @@ impl MoveList<ExtMove> {
         }
     }
 
-    fn remove(&mut self, m: Move) {
-        if self.contains(m) {
-            unsafe {
-                self._movelist[self._movelist.iter().position(|&x| x.m == m).unwrap_unchecked()] = self._movelist[self.off_the_end - 1];
-            }
-            self.off_the_end -= 1;
-            self.bitboard.remove(m);
-        }
-    }
-    
     fn max(&self, start: usize, end: usize) -> usize {
         let mut max_i: usize = 0;
         let mut max_val: Score = SCORE_NONE;
 
         for (i, em) in self._movelist.iter().enumerate() {
-            if i >= start && i < end {
-                if em.s != SCORE_NONE {
-                    if max_val == SCORE_NONE || max_val < em.s {
-                        max_i = i;
-                        max_val = em.s;
-                    }
-                }
+            if i >= start && i < end && em.s != SCORE_NONE && (max_val == SCORE_NONE || max_val < em.s) {
+                max_i = i;
+                max_val = em.s;
             }
         }
 
@@ -734,277 +888,269 @@ pub enum GenType {
 }
 
 pub trait MoveGenArgs {
-    fn new(self) -> MoveGen;
+    fn make(self, bd: &mut Board) -> MoveGen;
 }
 
 impl MoveGenArgs for () {
-    fn new(self) -> MoveGen {
-        unsafe {
-            let (stg, ttm, rnode, p, karr1, karr2, cm) = (Stage::MainTT, MOVE_NONE, false, DEPTH_NONE, MOVE_NONE, MOVE_NONE, MOVE_NONE);
-            let piece_cnt: usize = BOARD.as_ref().unwrap().piece_cnt;
-            // Late movelist update
-            if !BOARD.as_ref().unwrap().updated_move_list[piece_cnt] {
-                let last_move = BOARD.as_ref().unwrap().last_move(1);
-                BOARD.as_mut().unwrap().update_movelist(last_move);
-                BOARD.as_mut().unwrap().updated_move_list[piece_cnt] = true;
-            }
-            let tt_move: Move = if ttm != MOVE_NONE && BOARD.as_ref().unwrap().m_list_stack[piece_cnt].contains(ttm) {ttm} else {MOVE_NONE};
-    
-            MoveGen {
-                movelist: MoveList::<ExtMove>::new(),
-                picked: 0,
-                stage: stg + if tt_move == MOVE_NONE {1} else {0},
-                tt_move,
-                root_node: rnode,
-                ply: p,
-                killers: if is_ok_move(karr1) && is_ok_move(karr2) {[karr1, karr2]} else {[MOVE_NONE, MOVE_NONE]},
-                counter_move: cm,
-            }
+    fn make(self, bd: &mut Board) -> MoveGen {
+        let (stg, ttm, rnode, p, karr1, karr2, cm) = (Stage::MainTT, MOVE_NONE, false, DEPTH_NONE, MOVE_NONE, MOVE_NONE, MOVE_NONE);
+        let piece_cnt: usize = bd.piece_cnt;
+        // Late movelist update
+        if !bd.updated_move_list[piece_cnt] {
+            let last_move = bd.last_move(1);
+            bd.update_movelist(last_move);
+            bd.updated_move_list[piece_cnt] = true;
+        }
+        let tt_move: Move = if ttm != MOVE_NONE && bd.m_list_stack[piece_cnt].contains(ttm) {ttm} else {MOVE_NONE};
+
+        MoveGen {
+            movelist: MoveList::<ExtMove>::new(),
+            picked: 0,
+            stage: stg + if tt_move == MOVE_NONE {1} else {0},
+            tt_move,
+            root_node: rnode,
+            ply: p,
+            killers: if is_ok_move(karr1) && is_ok_move(karr2) {[karr1, karr2]} else {[MOVE_NONE, MOVE_NONE]},
+            counter_move: cm,
         }
     }
 }
 
 impl MoveGenArgs for (Stage, Move, bool) {
-    fn new(self) -> MoveGen {
-        unsafe {
-            let (stg, ttm, rnode) = self;
-            let (p, karr1, karr2, cm) = (DEPTH_NONE, MOVE_NONE, MOVE_NONE, MOVE_NONE);
-            // Late movelist update
-            if !BOARD.as_ref().unwrap().updated_move_list[BOARD.as_ref().unwrap().piece_cnt] {
-                let last_move = BOARD.as_ref().unwrap().last_move(1);
-                BOARD.as_mut().unwrap().update_movelist(last_move);
-                BOARD.as_mut().unwrap().updated_move_list[BOARD.as_ref().unwrap().piece_cnt] = true;
-            }
-            let tt_move: Move = if ttm != MOVE_NONE && BOARD.as_ref().unwrap().m_list_stack[BOARD.as_ref().unwrap().piece_cnt].contains(ttm) {ttm} else {MOVE_NONE};
-    
-            MoveGen {
-                movelist: MoveList::<ExtMove>::new(),
-                picked: 0,
-                stage: stg + if tt_move == MOVE_NONE {1} else {0},
-                tt_move,
-                root_node: rnode,
-                ply: p,
-                killers: if is_ok_move(karr1) && is_ok_move(karr2) {[karr1, karr2]} else {[MOVE_NONE, MOVE_NONE]},
-                counter_move: cm,
-            }
+    fn make(self, bd: &mut Board) -> MoveGen {
+        let (stg, ttm, rnode) = self;
+        let (p, karr1, karr2, cm) = (DEPTH_NONE, MOVE_NONE, MOVE_NONE, MOVE_NONE);
+        // Late movelist update
+        if !bd.updated_move_list[bd.piece_cnt] {
+            let last_move = bd.last_move(1);
+            bd.update_movelist(last_move);
+            bd.updated_move_list[bd.piece_cnt] = true;
+        }
+        let tt_move: Move = if ttm != MOVE_NONE && bd.m_list_stack[bd.piece_cnt].contains(ttm) {ttm} else {MOVE_NONE};
+
+        MoveGen {
+            movelist: MoveList::<ExtMove>::new(),
+            picked: 0,
+            stage: stg + if tt_move == MOVE_NONE {1} else {0},
+            tt_move,
+            root_node: rnode,
+            ply: p,
+            killers: if is_ok_move(karr1) && is_ok_move(karr2) {[karr1, karr2]} else {[MOVE_NONE, MOVE_NONE]},
+            counter_move: cm,
         }
     }
 }
 
 impl MoveGenArgs for (Stage, Move, bool, Depth, Move, Move, Move) {
-    fn new(self) -> MoveGen {
-        unsafe {
-            let (stg, ttm, rnode, p, karr1, karr2, cm) = self;
-            let piece_cnt: usize = BOARD.as_ref().unwrap().piece_cnt;
-            // Late movelist update
-            if !BOARD.as_ref().unwrap().updated_move_list[piece_cnt] {
-                let last_move = BOARD.as_ref().unwrap().last_move(1);
-                BOARD.as_mut().unwrap().update_movelist(last_move);
-                BOARD.as_mut().unwrap().updated_move_list[piece_cnt] = true;
-            }
-            let tt_move: Move = if ttm != MOVE_NONE && BOARD.as_ref().unwrap().m_list_stack[piece_cnt].contains(ttm) {ttm} else {MOVE_NONE};
-    
-            MoveGen {
-                movelist: MoveList::<ExtMove>::new(),
-                picked: 0,
-                stage: stg + if tt_move == MOVE_NONE {1} else {0},
-                tt_move,
-                root_node: rnode,
-                ply: p,
-                killers: if is_ok_move(karr1) && is_ok_move(karr2) {[karr1, karr2]} else {[MOVE_NONE, MOVE_NONE]},
-                counter_move: cm,
-            }
+    fn make(self, bd: &mut Board) -> MoveGen {
+        let (stg, ttm, rnode, p, karr1, karr2, cm) = self;
+        let piece_cnt: usize = bd.piece_cnt;
+        // Late movelist update
+        if !bd.updated_move_list[piece_cnt] {
+            let last_move = bd.last_move(1);
+            bd.update_movelist(last_move);
+            bd.updated_move_list[piece_cnt] = true;
+        }
+        let tt_move: Move = if ttm != MOVE_NONE && bd.m_list_stack[piece_cnt].contains(ttm) {ttm} else {MOVE_NONE};
+
+        MoveGen {
+            movelist: MoveList::<ExtMove>::new(),
+            picked: 0,
+            stage: stg + if tt_move == MOVE_NONE {1} else {0},
+            tt_move,
+            root_node: rnode,
+            ply: p,
+            killers: if is_ok_move(karr1) && is_ok_move(karr2) {[karr1, karr2]} else {[MOVE_NONE, MOVE_NONE]},
+            counter_move: cm,
         }
     }
 }
 
 impl MoveGen {
-    pub fn new<T>(args: T) -> MoveGen where T: MoveGenArgs {
-        args.new()
+    pub fn new<T>(bd: &mut Board, args: T) -> MoveGen where T: MoveGenArgs {
+        args.make(bd)
     }
 
     fn begin(&self) -> ExtMove {
-        return self.movelist.begin(None);
+        self.movelist.begin(None)
     }
 
     fn end(&self) -> ExtMove {
-        return self.movelist.end(None);
+        self.movelist.end(None)
     }
 
     fn current(&self) -> ExtMove {
-        return self.movelist.begin(Some(self.picked as usize));
+        self.movelist.begin(Some(self.picked))
     }
 
     pub fn size(&self) -> i32 {
-        return self.movelist.size();
+        self.movelist.size()
     }
 
-    pub fn generate(&mut self, gt: GenType) -> ExtMove {
-        unsafe {
-            match gt {
-                GenType::WLD => {
-                    let mut offset: Score = 0;
-
-                    BOARD.as_ref().unwrap().check_wld(&mut offset);
-
-                    let m_list_stack: MoveList<Move> = BOARD.as_ref().unwrap().m_list_stack[BOARD.as_ref().unwrap().piece_cnt];
-
-                    // If we have F4 or B4, we form C5 to win
-                    if BOARD.as_ref().unwrap().query(BOARD.as_ref().unwrap().side_to_move, Material::F4) > 0 || BOARD.as_ref().unwrap().query(BOARD.as_ref().unwrap().side_to_move, Material::B4) > 0 {
-                        for i in 0..m_list_stack.size() {
-                            let m: Move = m_list_stack.begin(Some(i as usize));
-                            if BOARD.as_ref().unwrap().query_us_inc(BOARD.as_ref().unwrap().side_to_move, m, Material::C5) > 0 {
-                                self.movelist.insert(m, Some(WIN_SCORE - offset as Score));
-                                return self.begin();
-                            }
-                        }
-                    }
+    pub fn generate(&mut self, bd: &mut Board, gt: GenType) -> ExtMove {
+        match gt {
+            GenType::WLD => {
+                let mut offset: Score = 0;
 
-                    // If opponent has F4, we defend and lose. Cannot call pbd->query in this case
-                    if BOARD.as_ref().unwrap().query(BOARD.as_ref().unwrap().oppo_to_move, Material::F4) > 0 {
-                        for i in 0..m_list_stack.size() {
-                            let m: Move = m_list_stack.begin(Some(i as usize));
-                            BOARD.as_mut().unwrap().do_move(m);
-                            if BOARD.as_ref().unwrap().query_us_inc(BOARD.as_ref().unwrap().side_to_move, m, Material::F4) < 0 {
-                                BOARD.as_mut().unwrap().undo_move();
-                                self.movelist.insert(m, None);
-                                return self.begin();
-                            }
-                            BOARD.as_mut().unwrap().undo_move();
-                        }
-                    }
+                bd.check_wld(&mut offset);
+
+                let m_list_stack: MoveList<Move> = bd.m_list_stack[bd.piece_cnt];
 
-                    // If opponent has several B4, we defend and lose
-                    if BOARD.as_ref().unwrap().query(BOARD.as_ref().unwrap().oppo_to_move, Material::B4) >= 2 {
-                        self.movelist.insert(BOARD.as_ref().unwrap().defend_b4(), Some(-WIN_SCORE + offset as Score));
-                        return self.begin();
+                // If we have F4 or B4, we form C5 to win
+                if bd.query(bd.side_to_move, Material::F4) > 0 || bd.query(bd.side_to_move, Material::B4) > 0 {
+                    for i in 0..m_list_stack.size() {
+                        let m: Move = m_list_stack.begin(Some(i as usize));
+                        if bd.is_winning_move(m) {
+                            self.movelist.insert(m, Some(WIN_SCORE - offset as Score));
+                            return self.begin();
+                        }
                     }
+                }
 
-                    // If we have F3 and neither has B4, we form F4 to win
-                    if BOARD.as_ref().unwrap().query(BOARD.as_ref().unwrap().side_to_move, Material::F3) > 0 && BOARD.as_ref().unwrap().query(BOARD.as_ref().unwrap().oppo_to_move, Material::B4) == 0 {
-                        for i in BOARD.as_ref().unwrap().f3_stack[BOARD.as_ref().unwrap().piece_cnt].iter() {
-                            if i.color == BOARD.as_ref().unwrap().side_to_move {
-                                self.movelist.insert(i.f4a[0], Some(WIN_SCORE - offset as Score));
-                                return self.begin();
-                            }
+                // If opponent has F4, we defend and lose. Cannot call pbd->query in this case
+                if bd.query(bd.oppo_to_move, Material::F4) > 0 {
+                    for i in 0..m_list_stack.size() {
+                        let m: Move = m_list_stack.begin(Some(i as usize));
+                        bd.do_move(m);
+                        if bd.query_us_inc(bd.side_to_move, m, Material::F4) < 0 {
+                            bd.undo_move();
+                            self.movelist.insert(m, None);
+                            return self.begin();
                         }
+                        bd.undo_move();
                     }
+                }
 
-                    ExtMove {m: MOVE_NONE, s: SCORE_NONE}
-                },
-                GenType::DefendB4 => {
-                    self.movelist.insert(BOARD.as_ref().unwrap().defend_b4(), None);
-                
+                // If opponent has several B4, we defend and lose
+                if bd.query(bd.oppo_to_move, Material::B4) >= 2 {
+                    self.movelist.insert(bd.defend_b4(), Some(-WIN_SCORE + offset as Score));
                     return self.begin();
-                },
-                GenType::DefendF3 => {
-                    for i in BOARD.as_ref().unwrap().f3_stack[BOARD.as_ref().unwrap().piece_cnt].iter() {
-                        for m in i.f3d.iter() {
-                            if *m != MOVE_NONE {
-                                self.movelist.insert(*m, Some(self.score_of(*m) + BONUS_F3D));
-                            }
+                }
+
+                // If we have F3 and neither has B4, we form F4 to win
+                if bd.query(bd.side_to_move, Material::F3) > 0 && bd.query(bd.oppo_to_move, Material::B4) == 0 {
+                    for i in bd.f3_stack[bd.piece_cnt].iter() {
+                        if i.color == bd.side_to_move {
+                            self.movelist.insert(i.f4a[0], Some(WIN_SCORE - offset as Score));
+                            return self.begin();
                         }
                     }
+                }
 
-                    let m_list_stack: MoveList<i16> = BOARD.as_ref().unwrap().m_list_stack[BOARD.as_ref().unwrap().piece_cnt];
-                    for i in 0..m_list_stack.size() {
-                        let m: Move = m_list_stack.begin(Some(i as usize));
-                        if BOARD.as_ref().unwrap().query_us_inc(BOARD.as_ref().unwrap().side_to_move, m, Material::B4) > 0 {
-                            self.movelist.insert(m, Some(self.score_of(m)));
+                ExtMove {m: MOVE_NONE, s: SCORE_NONE}
+            },
+            GenType::DefendB4 => {
+                self.movelist.insert(bd.defend_b4(), None);
+            
+                self.begin()
+            },
+            GenType::DefendF3 => {
+                for i in bd.f3_stack[bd.piece_cnt].iter() {
+                    for m in i.f3d.iter() {
+                        if *m != MOVE_NONE {
+                            self.movelist.insert(*m, Some(self.score_of(bd, *m) + BONUS_F3D));
                         }
                     }
+                }
 
-                    return self.begin();
-                },
-                GenType::DEFAULT => {
-                    let m_list_stack: MoveList<Move> = BOARD.as_ref().unwrap().m_list_stack[BOARD.as_ref().unwrap().piece_cnt];
-                    for i in 0..m_list_stack.size() {
-                        let m: Move = m_list_stack.begin(Some(i as usize));
-                        self.movelist.insert(m, Some(self.score_of(m)));
+                let m_list_stack: MoveList<i16> = bd.m_list_stack[bd.piece_cnt];
+                for i in 0..m_list_stack.size() {
+                    let m: Move = m_list_stack.begin(Some(i as usize));
+                    if bd.query_us_inc(bd.side_to_move, m, Material::B4) > 0 {
+                        self.movelist.insert(m, Some(self.score_of(bd, m)));
                     }
-                
-                    return self.begin();
-                },
-                GenType::LARGE => {
-                    for i in 0..BOARD.as_ref().unwrap().piece_cnt {
-                        let m: Move = BOARD.as_ref().unwrap().piece_list[i as usize];
-                        for i in N3 {
-                            if BOARD.as_ref().unwrap().is_empty(m + i) && !self.movelist.contains(m + i) {
-                                self.movelist.insert(m + i, Some(self.score_of(m + i)));
-                            }
+                }
+
+                self.begin()
+            },
+            GenType::DEFAULT => {
+                let m_list_stack: MoveList<Move> = bd.m_list_stack[bd.piece_cnt];
+                for i in 0..m_list_stack.size() {
+                    let m: Move = m_list_stack.begin(Some(i as usize));
+                    self.movelist.insert(m, Some(self.score_of(bd, m)));
+                }
+            
+                self.begin()
+            },
+            GenType::LARGE => {
+                for i in 0..bd.piece_cnt {
+                    let m: Move = bd.piece_list[i];
+                    for i in N3 {
+                        if bd.is_empty(m + i) && !self.movelist.contains(m + i) {
+                            self.movelist.insert(m + i, Some(self.score_of(bd, m + i)));
                         }
                     }
-                
-                    return self.begin();
-                },
-                GenType::MAIN => {
-                    if BOARD.as_ref().unwrap().query(BOARD.as_ref().unwrap().oppo_to_move, Material::B4) > 0 {
-                        self.generate(GenType::DefendB4);
-                    } else if BOARD.as_ref().unwrap().query(BOARD.as_ref().unwrap().oppo_to_move, Material::F3) > 0 {
-                        self.generate(GenType::DefendF3);
-                    } else if self.ply < 2 && BOARD.as_ref().unwrap().piece_cnt < 5 {
-                        self.generate(GenType::LARGE);
-                    } else {
-                        self.generate(GenType::DEFAULT);
+                }
+            
+                self.begin()
+            },
+            GenType::MAIN => {
+                if bd.query(bd.oppo_to_move, Material::B4) > 0 {
+                    self.generate(bd, GenType::DefendB4);
+                } else if bd.query(bd.oppo_to_move, Material::F3) > 0 {
+                    self.generate(bd, GenType::DefendF3);
+                } else if self.ply < 2 && bd.piece_cnt < 5 {
+                    self.generate(bd, GenType::LARGE);
+                } else {
+                    self.generate(bd, GenType::DEFAULT);
+                }
+            
+                self.begin()
+            },
+            GenType::TTMOVE => {
+                self.movelist.insert(self.tt_move, None);
+
+                self.begin()
+            },
+            GenType::VCFROOT => {
+                if bd.query(bd.oppo_to_move, Material::B4) > 0 {
+                    if bd.query_us_inc(bd.side_to_move, bd.defend_b4(), Material::F4) + bd.query_us_inc(bd.side_to_move, bd.defend_b4(), Material::B4) > 0 {
+                        self.movelist.insert(bd.defend_b4(), None);
                     }
-                
-                    return self.begin();
-                },
-                GenType::TTMOVE => {
-                    self.movelist.insert(self.tt_move, None);
+                } else {
+                    let m_list_stack: MoveList<Move> = bd.m_list_stack[bd.piece_cnt];
+                    
+                    for i in 0..m_list_stack.size() {
+                        let m: Move = m_list_stack.begin(Some(i as usize));
 
-                    return self.begin();
-                },
-                GenType::VCFROOT => {
-                    if BOARD.as_ref().unwrap().query(BOARD.as_ref().unwrap().oppo_to_move, Material::B4) > 0 {
-                        if BOARD.as_ref().unwrap().query_us_inc(BOARD.as_ref().unwrap().side_to_move, BOARD.as_ref().unwrap().defend_b4(), Material::F4) + BOARD.as_ref().unwrap().query_us_inc(BOARD.as_ref().unwrap().side_to_move, BOARD.as_ref().unwrap().defend_b4(), Material::B4) > 0 {
-                            self.movelist.insert(BOARD.as_ref().unwrap().defend_b4(), None);
-                        }
-                    } else {
-                        let m_list_stack: MoveList<Move> = BOARD.as_ref().unwrap().m_list_stack[BOARD.as_ref().unwrap().piece_cnt];
-                        
-                        for i in 0..m_list_stack.size() {
-                            let m: Move = m_list_stack.begin(Some(i as usize));
-
-                            if BOARD.as_ref().unwrap().query_us_inc(BOARD.as_ref().unwrap().side_to_move, m, Material::B4) > 0 && (BOARD.as_ref().unwrap().query_us_inc(BOARD.as_ref().unwrap().side_to_move, m, Material::B4) >= 2 || BOARD.as_ref().unwrap().query_us_inc(BOARD.as_ref().unwrap().side_to_move, m, Material::F3) > 0 || BOARD.as_ref().unwrap().query_us_inc(BOARD.as_ref().unwrap().side_to_move, m, Material::B3) > 0 || BOARD.as_ref().unwrap().query_vcf(BOARD.as_ref().unwrap().side_to_move, m) > 0) {
-                                self.movelist.insert(m, Some(BOARD.as_ref().unwrap().see_of(m)));
-                            }
+                        if bd.query_us_inc(bd.side_to_move, m, Material::B4) > 0 && (bd.query_us_inc(bd.side_to_move, m, Material::B4) >= 2 || bd.query_us_inc(bd.side_to_move, m, Material::F3) > 0 || bd.query_us_inc(bd.side_to_move, m, Material::B3) > 0 || bd.query_vcf(bd.side_to_move, m) > 0) {
+                            self.movelist.insert(m, Some(bd.see_of(m)));
                         }
                     }
+                }
 
-                    return self.begin();
-                },
-                GenType::VCFCHILD => {
-                    if BOARD.as_ref().unwrap().query(BOARD.as_ref().unwrap().oppo_to_move, Material::B4) > 0 {
-                        if BOARD.as_ref().unwrap().query_us_inc(BOARD.as_ref().unwrap().side_to_move, BOARD.as_ref().unwrap().defend_b4(), Material::F4) + BOARD.as_ref().unwrap().query_us_inc(BOARD.as_ref().unwrap().side_to_move, BOARD.as_ref().unwrap().defend_b4(), Material::B4) > 0 {
-                            self.movelist.insert(BOARD.as_ref().unwrap().defend_b4(), None);
-                        }
-                    } else {
+                self.begin()
+            },
+            GenType::VCFCHILD => {
+                if bd.query(bd.oppo_to_move, Material::B4) > 0 {
+                    if bd.query_us_inc(bd.side_to_move, bd.defend_b4(), Material::F4) + bd.query_us_inc(bd.side_to_move, bd.defend_b4(), Material::B4) > 0 {
+                        self.movelist.insert(bd.defend_b4(), None);
+                    }
+                } else {
 
-                        for i in N4 {
-                            let m: Move = BOARD.as_ref().unwrap().last_move(2) + i;
-                            if BOARD.as_ref().unwrap().is_empty(m) && BOARD.as_ref().unwrap().query_us_inc(BOARD.as_ref().unwrap().side_to_move, m, Material::B4) > 0
-                                && (BOARD.as_ref().unwrap().query_us_inc(BOARD.as_ref().unwrap().side_to_move, m, Material::B4) >= 2
-                                    || BOARD.as_ref().unwrap().query_us_inc(BOARD.as_ref().unwrap().side_to_move, m, Material::F3) > 0
-                                    || BOARD.as_ref().unwrap().query_us_inc(BOARD.as_ref().unwrap().side_to_move, m, Material::B3) > 0
-                                    || BOARD.as_ref().unwrap().query_vcf(BOARD.as_ref().unwrap().side_to_move, m) > 0) {
-                                self.movelist.insert(m, Some(BOARD.as_ref().unwrap().see_of(m)));
-                            }
+                    for i in N4 {
+                        let m: Move = bd.last_move(2) + i;
+                        if bd.is_empty(m) && bd.query_us_inc(bd.side_to_move, m, Material::B4) > 0
+                            && (bd.query_us_inc(bd.side_to_move, m, Material::B4) >= 2
+                                || bd.query_us_inc(bd.side_to_move, m, Material::F3) > 0
+                                || bd.query_us_inc(bd.side_to_move, m, Material::B3) > 0
+                                || bd.query_vcf(bd.side_to_move, m) > 0) {
+                            self.movelist.insert(m, Some(bd.see_of(m)));
                         }
                     }
+                }
 
-                    return self.begin();
-                },
-            }
+                self.begin()
+            },
         }
     }
 
-    unsafe fn score_of(&self, m: Move) -> Score {
-        let mut score: Score = BOARD.as_ref().unwrap().see_of(m);
+    fn score_of(&self, bd: &Board, m: Move) -> Score {
+        let mut score: Score = bd.see_of(m);
         let mut dist: [i32; 4] = [0; 4];
 
-        for i in 0..4 {
-            dist[i] = if BOARD.as_ref().unwrap().piece_cnt >= i + 1 {_distance_between(m, BOARD.as_ref().unwrap().last_move(i + 1))} else {0};
+        for (i, d) in dist.iter_mut().enumerate() {
+            *d = if bd.piece_cnt > i {_distance_between(m, bd.last_move(i + 1))} else {0};
         }
     
         if dist[0] > 4 && dist[1] > 4 && dist[2] > 4 && dist[3] > 4 {
@@ -1015,16 +1161,16 @@ impl MoveGen {
 
         score += if m == self.killers[0] || m == self.killers[1] || m == self.counter_move {BONUS_REFUTATION} else {0};
     
-        return score;
+        score
     }
 
-    pub fn next_move(&mut self) -> ExtMove {
+    pub fn next_move(&mut self, bd: &mut Board) -> ExtMove {
         let mut ret: ExtMove = ExtMove {m: MOVE_NONE, s: SCORE_NONE};
 
         loop {
             match self.stage {
                 Stage::MainTT | Stage::VcfTT => {
-                    self.generate(GenType::TTMOVE);
+                    self.generate(bd, GenType::TTMOVE);
                     self.picked += 1;
                     self.stage = self.stage + 1;
 
@@ -1032,14 +1178,14 @@ impl MoveGen {
                     break;
                 }
                 Stage::MainINIT => {
-                    self.generate(GenType::MAIN);
+                    self.generate(bd, GenType::MAIN);
                     self.stage = self.stage + 1;
                 }
                 Stage::VcfINIT => {
                     if self.root_node {
-                        self.generate(GenType::VCFROOT);
+                        self.generate(bd, GenType::VCFROOT);
                     } else {
-                        self.generate(GenType::VCFCHILD);
+                        self.generate(bd, GenType::VCFCHILD);
                     }
                     self.stage = self.stage + 1;
                 }
@@ -1048,9 +1194,9 @@ impl MoveGen {
                         self.stage = self.stage + 1;
                         continue;
                     }
-                    let bestIt = self.movelist.max(self.picked, self.size() as usize);
-                    ret = self.movelist.begin(Some(bestIt));
-                    self.movelist.swap(self.picked, bestIt);
+                    let best_it = self.movelist.max(self.picked, self.size() as usize);
+                    ret = self.movelist.begin(Some(best_it));
+                    self.movelist.swap(self.picked, best_it);
                     self.picked += 1;
                     
                     break;
@@ -1074,6 +1220,21 @@ pub enum Color {
     None,
 }
 
+// Winning and foul rules of a game.
+// Freestyle: five or more wins.
+// Standard: exactly five wins, overlines do not.
+// Renju: like standard, and Black additionally loses by double-three, double-four or overline.
+// Caro: five or more wins unless both ends are blocked by the opponent.
+#[wasm_bindgen]
+#[derive(EnumString, Clone, Copy, PartialEq, Debug)]
+#[strum(ascii_case_insensitive)]
+pub enum Rule {
+    Freestyle = 0,
+    Standard = 1,
+    Renju = 2,
+    Caro = 3,
+}
+
 #[derive(EnumIter, EnumCountMacro, Clone, Copy, PartialEq)]
 pub enum Direction {
     DRank,
@@ -1084,19 +1245,19 @@ pub enum Direction {
 
 #[derive(Clone, Copy)]
 pub struct DirectionCount {
-    DRank: i32,
-    DADiag: i32,
-    DFile: i32,
-    DMDiag: i32,
+    rank: i32,
+    adiag: i32,
+    file: i32,
+    mdiag: i32,
 }
 
 impl DirectionCount {
     fn new() -> DirectionCount {
         Self {
-            DRank: 0,
-            DADiag: 0,
-            DFile: 0,
-            DMDiag: 0,
+            rank: 0,
+            adiag: 0,
+            file: 0,
+            mdiag: 0,
         }
     }
 }
@@ -1106,10 +1267,10 @@ impl Index<Direction> for DirectionCount {
 
     fn index(&self, direction: Direction) -> &Self::Output {
         match direction {
-            Direction::DRank => &self.DRank,
-            Direction::DADiag => &self.DADiag,
-            Direction::DFile => &self.DFile,
-            Direction::DMDiag => &self.DMDiag,
+            Direction::DRank => &self.rank,
+            Direction::DADiag => &self.adiag,
+            Direction::DFile => &self.file,
+            Direction::DMDiag => &self.mdiag,
         }
     }
 }
@@ -1117,10 +1278,10 @@ impl Index<Direction> for DirectionCount {
 impl IndexMut<Direction> for DirectionCount {
     fn index_mut(&mut self, direction: Direction) -> &mut Self::Output {
         match direction {
-            Direction::DRank => &mut self.DRank,
-            Direction::DADiag => &mut self.DADiag,
-            Direction::DFile => &mut self.DFile,
-            Direction::DMDiag => &mut self.DMDiag,
+            Direction::DRank => &mut self.rank,
+            Direction::DADiag => &mut self.adiag,
+            Direction::DFile => &mut self.file,
+            Direction::DMDiag => &mut self.mdiag,
         }
     }
 }
@@ -1228,8 +1389,8 @@ const N4: [Move; 32] = [
 ];
 
 impl F3Pack {
-    const f4a_size: i32 = 2;
-    const f3d_size: i32 = 3;
+    const F4A_SIZE: i32 = 2;
+    const F3D_SIZE: i32 = 3;
     fn new(c: Color, m: Move, d: Direction, ind: i32) -> F3Pack {
         Self {
             color: c,
@@ -1243,10 +1404,10 @@ impl F3Pack {
     }
 
     fn valid(&self) -> bool {
-        if self.f4a[0] == MOVE_NONE || self.f3d[0] == MOVE_NONE {false} else {true}
+        self.f4a[0] != MOVE_NONE && self.f3d[0] != MOVE_NONE
     }
 
-    unsafe fn update_renju(&mut self) {
+    fn update_renju(&mut self, bd: &Board) {
         let mut ind1: i32 = 0;
         let mut ind2: i32 = 0;
 
@@ -1255,13 +1416,13 @@ impl F3Pack {
 
         for i in -4..5 {
             let m = self.m + D[self.direction as usize] * i;
-            if BOARD.as_ref().unwrap().is_empty(m) {
-                if ind1 < F3Pack::f4a_size && BOARD.as_ref().unwrap().query_us_inc(self.color, m, Material::C6) == 0 && BOARD.as_ref().unwrap().query_us_inc(self.color, m, Material::F4) == 1 && BOARD.as_ref().unwrap().query_us_inc(self.color, m, Material::B4) == 0 && BOARD.as_ref().unwrap().query_us_inc(self.color, m, Material::F3) <= 1 {
+            if bd.is_empty(m) {
+                if ind1 < F3Pack::F4A_SIZE && bd.query_us_inc(self.color, m, Material::C6) == 0 && bd.query_us_inc(self.color, m, Material::F4) == 1 && bd.query_us_inc(self.color, m, Material::B4) == 0 && bd.query_us_inc(self.color, m, Material::F3) <= 1 {
                     self.f4a[ind1 as usize] = m;
                     ind1 += 1;
                 }
 
-                if ind2 < F3Pack::f3d_size && BOARD.as_ref().unwrap().query_opp_dec(if self.color == Color::White {Color::Black} else {Color::White}, m, Material::F3) > 0 {
+                if ind2 < F3Pack::F3D_SIZE && bd.query_opp_dec(if self.color == Color::White {Color::Black} else {Color::White}, m, Material::F3) > 0 {
                     self.f3d[ind2 as usize] = m;
                     ind2 += 1;
                 }
@@ -1269,7 +1430,7 @@ impl F3Pack {
         }
     }
 
-    unsafe fn update_free(&mut self) {
+    fn update_free(&mut self, bd: &Board) {
         let mut ind1: i32 = 0;
         let mut ind2: i32 = 0;
 
@@ -1278,13 +1439,13 @@ impl F3Pack {
 
         for i in -4..5 {
             let m = self.m + D[self.direction as usize] * i;
-            if BOARD.as_ref().unwrap().is_empty(m) {
-                if ind1 < F3Pack::f4a_size && BOARD.as_ref().unwrap().query_us_inc(self.color, m, Material::F4) > 0 {
+            if bd.is_empty(m) {
+                if ind1 < F3Pack::F4A_SIZE && bd.query_us_inc(self.color, m, Material::F4) > 0 {
                     self.f4a[ind1 as usize] = m;
                     ind1 += 1;
                 }
 
-                if ind2 < F3Pack::f3d_size && BOARD.as_ref().unwrap().query_opp_dec(if self.color == Color::White {Color::Black} else {Color::White}, m, Material::F3) > 0 {
+                if ind2 < F3Pack::F3D_SIZE && bd.query_opp_dec(if self.color == Color::White {Color::Black} else {Color::White}, m, Material::F3) > 0 {
                     self.f3d[ind2 as usize] = m;
                     ind2 += 1;
                 }
@@ -1331,14 +1492,16 @@ type F3Packs = Vec<F3Pack>;
 pub struct Board {
     _board: [Color; MOVE_CAPACITY as usize],
     zobrists: [[ZobristKey; MOVE_CAPACITY as usize]; 2],
+    zobrist_side: ZobristKey,
     mat: [[MaterialCount; 2]; STACK_SIZE as usize],
     mat_inc: [MaterialCount; 2],
-    f3_formed_cnt: [i32; 2],
     piece_list: [Move; MOVE_SIZE as usize],
+    // Passes played since the stone with the same index
+    pass_cnt: [u16; STACK_SIZE as usize],
     updated_move_list: [bool; STACK_SIZE as usize],
     updated_interval: [bool; STACK_SIZE as usize],
     m_list_stack: [MoveList<Move>; STACK_SIZE as usize],
-    see: [[[u32; BOARD_SIDE as usize]; VECTOR_SIZE as usize]; 2],
+    see: [[[u32; BOARD_SIDE_MAX as usize]; VECTOR_SIZE as usize]; 2],
     index_on_table: [DirectionCount; MOVE_CAPACITY as usize],
     index_of_table: [DirectionCount; MOVE_CAPACITY as usize],
     b4d_stack: [Move; STACK_SIZE as usize],
@@ -1346,34 +1509,47 @@ pub struct Board {
     score: [[Score; 2]; STACK_SIZE as usize],
     see_table: [Score; 16384],
     interval: [[[Interval; BOARD_SIZE as usize]; VECTOR_SIZE as usize]; 2],
-    see_stack: [[[[u32; BOARD_SIDE as usize]; 4]; 2]; STACK_SIZE as usize],
+    see_stack: [[[[u32; BOARD_SIDE_MAX as usize]; 4]; 2]; STACK_SIZE as usize],
     vector_board: [[u32; VECTOR_SIZE as usize]; 2],
+    rule: Rule,
+    side: i32,
     pub side_to_move: Color,
     pub oppo_to_move: Color,
     pub key: u64,
     pub piece_cnt: usize,
+    // Stones put on by `setup`, never judged as fouls
+    setup_cnt: usize,
 }
 
 pub fn reset_pv(pv: &mut Pv) {
     (*pv)[0] = MOVE_NONE;
 }
 
+/// # Safety
+/// `pv` must be `Some`.
 pub unsafe fn is_empty(pv: Option<&Pv>) -> bool {
     pv.unwrap_unchecked()[0] == MOVE_NONE
 }
 
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Board {
     pub fn new() -> Board {
         let mut prng = PRNG::new(1070372);
 
         let mut bd: Board = Self {
-            _board: array_init(|i| if is_ok_move(i as Move) {Color::Hide} else {Color::None} ),
+            _board: array_init(|i| if is_ok_move_on(i as Move, BOARD_SIDE) {Color::Hide} else {Color::None} ),
             key: 0,
             zobrists: array_init(|_| array_init(|_| prng.rand64())),
+            zobrist_side: prng.rand64(),
             mat: array_init(|_| array_init(|_| MaterialCount::new())),
             mat_inc: array_init(|_| MaterialCount::new()),
-            f3_formed_cnt: array_init(|_| 0),
             piece_list: array_init(|_| MOVE_NONE),
+            pass_cnt: array_init(|_| 0),
             updated_move_list: array_init(|_| false),
             updated_interval: array_init(|_| false),
             m_list_stack: array_init(|_| MoveList::<Move>::new()),
@@ -1389,7 +1565,10 @@ impl Board {
             interval: array_init(|_| array_init(|_| array_init(|_| Interval { begin_p: 0, end_p: 0 }))),
             see_stack: array_init(|_| array_init(|_| array_init(|_| array_init(|_| 0)))),
             vector_board: array_init(|_| array_init(|_| 0)),
+            rule: Rule::Renju,
+            side: BOARD_SIDE,
             piece_cnt: 0,
+            setup_cnt: 0,
         };
 
         bd.init();
@@ -1399,18 +1578,11 @@ impl Board {
     }
 
     fn init(&mut self) {
-        for d in Direction::iter() {
-            for m in 0..MOVE_CAPACITY as Move {
-                if is_ok_move(m) {
-                    self.index_of_table[m as usize][d] = index_of_helper(m, d);
-                    self.index_on_table[m as usize][d] = index_on_helper(m, d);
-                }
-            }
-        }
+        self.init_lines();
 
-        for i in 0..16384 as u32 {
+        for i in 0..16384u32 {
             for m in [Material::B4, Material::F3, Material::B3, Material::F2, Material::B2, Material::F1, Material::B1] {
-                if (i & ((1 as u32) << ((m as u32) - 3))) != 0 {
+                if (i & (1u32 << ((m as u32) - 3))) != 0 {
                     self.see_table[i as usize] += SEE_HELPER[m as usize];
                 }
             }
@@ -1420,18 +1592,79 @@ impl Board {
                     continue;
                 }
 
-                if (i & ((1 as u32) << ((m as u32) + 4))) != 0 {
+                if (i & (1u32 << ((m as u32) + 4))) != 0 {
                     self.see_table[i as usize] -= SEE_HELPER[m as usize];
                 }
             }
         }
     }
 
+    fn init_lines(&mut self) {
+        for d in Direction::iter() {
+            for m in 0..MOVE_CAPACITY as Move {
+                if is_ok_move_on(m, self.side) {
+                    self.index_of_table[m as usize][d] = index_of_helper(m, d, self.side);
+                    self.index_on_table[m as usize][d] = index_on_helper(m, d, self.side);
+                } else {
+                    self.index_of_table[m as usize][d] = 0;
+                    self.index_on_table[m as usize][d] = 0;
+                }
+            }
+        }
+    }
+
+    pub fn side(&self) -> i32 {
+        self.side
+    }
+
+    // Changes the board to `side` x `side` and clears it; returns false for unsupported sides
+    pub fn set_side(&mut self, side: i32) -> bool {
+        if !is_ok_side(side) {
+            return false;
+        }
+
+        self.side = side;
+        self.init_lines();
+        self.reset();
+
+        true
+    }
+
+    pub fn contains(&self, m: Move) -> bool {
+        is_ok_move_on(m, self.side)
+    }
+
+    // Clears the board and puts the given stones on it with `side_to_move` to move;
+    // returns false, leaving the board empty, if a stone is off the board or doubled
+    pub fn setup(&mut self, black: &[Move], white: &[Move], side_to_move: Color) -> bool {
+        self.reset();
+
+        let stones: Vec<Move> = black.iter().chain(white.iter()).cloned().collect();
+        if stones.iter().enumerate().any(|(i, m)| !self.contains(*m) || stones[..i].contains(m))
+            || (side_to_move != Color::Black && side_to_move != Color::White) {
+            return false;
+        }
+
+        for m in _setup_moves(black, white, side_to_move).iter() {
+            self.do_move(*m);
+        }
+        self.end_setup();
+
+        true
+    }
+
+    // Takes the stones on the board as a set-up position rather than moves
+    pub fn end_setup(&mut self) {
+        self.setup_cnt = self.piece_cnt;
+    }
+
     pub fn reset(&mut self) {
         self.piece_cnt = 0;
+        self.setup_cnt = 0;
         self.side_to_move = Color::Black;
         self.oppo_to_move = Color::White;
         self.key = 0;
+        self.pass_cnt[0] = 0;
 
         for __material in self.mat.iter_mut() {
             for ___material in __material.iter_mut() {
@@ -1472,15 +1705,15 @@ impl Board {
         self.m_list_stack[self.piece_cnt].reset();
 
         for m in 0..MOVE_CAPACITY as Move {
-            self._board[m as usize] = if is_ok_move(m) {Color::Hide} else {Color::None};
+            self._board[m as usize] = if self.contains(m) {Color::Hide} else {Color::None};
         }
 
-        for p in 0..2 as usize {
+        for p in 0..2usize {
             for m in 0..MOVE_CAPACITY as Move {
-                if is_ok_move(m) {
+                if self.contains(m) {
                     for d in Direction::iter() {
                         self.interval[p][self.index_of(m, d) as usize][self.index_on(m, d) as usize].set_begin(0);
-                        self.interval[p][self.index_of(m, d) as usize][self.index_on(m, d) as usize].set_end(_distance_between(_start_of(m, d), _end_of(m, d)) + 1);
+                        self.interval[p][self.index_of(m, d) as usize][self.index_on(m, d) as usize].set_end(_distance_between(_start_of(m, d, self.side), _end_of(m, d, self.side)) + 1);
                     }
                 }
             }
@@ -1489,7 +1722,7 @@ impl Board {
         for p in 0..2 {
             for d in Direction::iter() {
                 for m in 0..MOVE_CAPACITY as Move {
-                    if is_ok_move(m) {
+                    if self.contains(m) {
                         self.line_update_inc(p, m, oppo_direction(d),self.interval[p as usize][self.index_of(m, oppo_direction(d)) as usize][self.index_on(m, oppo_direction(d)) as usize]);
                     }
                 }           
@@ -1500,11 +1733,128 @@ impl Board {
         self.updated_move_list[self.piece_cnt] = true;
     }
 
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    // Every cached pattern depends on the rule, so the board is cleared
+    pub fn set_rule(&mut self, rule: Rule) {
+        self.rule = rule;
+        self.reset();
+    }
+
+    // Overlines count as five for `p` unless the rule asks for exactly five
+    fn pattern(&self, p: i32, ind: usize) -> &'static [u32; 16] {
+        let free: bool = match self.rule {
+            Rule::Freestyle | Rule::Caro => true,
+            Rule::Standard => false,
+            Rule::Renju => p == 1,
+        };
+
+        if free {&PATTERN_F[ind]} else {&PATTERN_S[ind]}
+    }
+
+    pub fn has_fouls(&self, color: Color) -> bool {
+        self.rule == Rule::Renju && color == Color::Black
+    }
+
+    // Ends of the run of `color` stones along `d` through `m`, counting `m` as one of them
+    fn run_through(&self, color: Color, m: Move, d: Direction) -> (Move, Move) {
+        let step: Move = D[d as usize];
+        let mut lo: Move = m;
+        let mut hi: Move = m;
+
+        while self._board[(lo - step) as usize] == color {
+            lo -= step;
+        }
+        while self._board[(hi + step) as usize] == color {
+            hi += step;
+        }
+
+        (lo, hi)
+    }
+
+    // Whether `color` playing (or having played) `m` makes a five that wins under caro
+    fn caro_five(&self, color: Color, m: Move) -> bool {
+        Direction::iter().any(|d| {
+            let (lo, hi) = self.run_through(color, m, d);
+            self.caro_run_wins(color, d, lo, hi)
+        })
+    }
+
+    // Five or more from `lo` to `hi` along `d`, not blocked by the opponent at both ends
+    fn caro_run_wins(&self, color: Color, d: Direction, lo: Move, hi: Move) -> bool {
+        let opp: Color = if color == Color::Black {Color::White} else {Color::Black};
+        let step: Move = D[d as usize];
+
+        _distance_between(lo, hi) + 1 >= 5 && (self._board[(lo - step) as usize] != opp || self._board[(hi + step) as usize] != opp)
+    }
+
+    // Under caro a line of `p` shut in by the opponent at both ends that is at most five long, or longer
+    // with at most one empty square, can only make a five blocked at both ends. The tables would count its
+    // threes, fours and fives, so it is skipped.
+    fn caro_dead(&self, p: i32, m: Move, d: Direction, itv: Interval) -> bool {
+        if self.rule != Rule::Caro {
+            return false;
+        }
+
+        let opp: Color = if p == 0 {Color::White} else {Color::Black};
+        let ion: i32 = self.index_on(m, d);
+        let before: Move = m + D[d as usize] * (itv.begin() - 1 - ion) as Move;
+        let after: Move = m + D[d as usize] * (itv.end() - ion) as Move;
+        let stones: i32 = self.query_vector_board(p, self.index_of(m, d), itv).count_ones() as i32;
+
+        self._board[before as usize] == opp && self._board[after as usize] == opp && (itv.length() <= 5 || stones + 1 >= itv.length())
+    }
+
+    // Overlines do not win for `color` under the rule
+    fn exact_five(&self, color: Color) -> bool {
+        self.rule == Rule::Standard || (self.rule == Rule::Renju && color == Color::Black)
+    }
+
+    // A winning run of `color` stones through `m` as its direction and ends
+    fn five_through(&self, color: Color, m: Move) -> Option<(Direction, Move, Move)> {
+        Direction::iter().map(|d| (d, self.run_through(color, m, d))).find(|(d, (lo, hi))| {
+            let len: i32 = _distance_between(*lo, *hi) + 1;
+            if self.rule == Rule::Caro {
+                self.caro_run_wins(color, *d, *lo, *hi)
+            } else if self.exact_five(color) {
+                len == 5
+            } else {
+                len >= 5
+            }
+        }).map(|(d, (lo, hi))| (d, lo, hi))
+    }
+
+    // The run that won for `color`, looking through the last stone first
+    fn five_of(&self, color: Color) -> Option<Five> {
+        let last: Option<Move> = if self.piece_cnt > 0 {Some(self.last_move(1))} else {None};
+        let (d, lo, hi) = last.into_iter().chain(self.piece_list[..self.piece_cnt].iter().cloned())
+            .filter(|m| self.get_color(m) == color)
+            .find_map(|m| self.five_through(color, m))?;
+
+        Some(Five {
+            direction: [_rank_of(lo + D[d as usize]) - _rank_of(lo), _file_of(lo + D[d as usize]) - _file_of(lo)],
+            stones: (0.._distance_between(lo, hi) + 1).map(|i| lo + D[d as usize] * i as Move).map(|x| [_rank_of(x), _file_of(x)]).collect(),
+        })
+    }
+
+    // Whether the side to move wins at once by playing `m`
+    pub fn is_winning_move(&self, m: Move) -> bool {
+        if self.rule == Rule::Caro {
+            self.caro_five(self.side_to_move, m)
+        } else {
+            self.query_us_inc(self.side_to_move, m, Material::C5) > 0
+        }
+    }
+
     fn get_color(&self, m: &Move) -> Color {
         self._board[*m as usize]
     }
 
     fn switch_side_to_move(&mut self) {
+        self.key ^= self.zobrist_side;
+
         match self.side_to_move {
             Color::White => {
                 self.side_to_move = Color::Black;
@@ -1522,7 +1872,9 @@ impl Board {
     //     self.key ^ self.zobrists[self.side_to_move as usize][m as usize]
     // }
 
+    // Cuts the intervals of the colour opposing the stone on `m`
     fn update_interval(&mut self, m: Move) {
+        let p: usize = if self.get_color(&m) == Color::Black {Color::White as usize} else {Color::Black as usize};
         let mut iof: i32;
         let mut ion: i32;
 
@@ -1530,12 +1882,12 @@ impl Board {
             iof = self.index_of(m, d);
             ion = self.index_on(m, d);
 
-            for i in self.interval[self.oppo_to_move as usize][iof as usize][ion as usize].begin()..ion {
-                self.interval[self.oppo_to_move as usize][iof as usize][i as usize].set_end(ion);
+            for i in self.interval[p][iof as usize][ion as usize].begin()..ion {
+                self.interval[p][iof as usize][i as usize].set_end(ion);
             }
 
-            for i in ion+1..self.interval[self.oppo_to_move as usize][iof as usize][ion as usize].end() {
-                self.interval[self.oppo_to_move as usize][iof as usize][i as usize].set_begin(ion + 1);
+            for i in ion+1..self.interval[p][iof as usize][ion as usize].end() {
+                self.interval[p][iof as usize][i as usize].set_begin(ion + 1);
             }
         }
     }
@@ -1545,111 +1897,121 @@ impl Board {
         let iof: i32 = self.index_of(m, d);
         let ion: i32 = self.index_on(m, d);
 
-        if itv.length() < 5 {
+        if itv.length() < 5 || self.caro_dead(p, m, d, itv) {
             for i in itv.begin()..itv.end() {
                 self.see[p as usize][iof as usize][i as usize] = 0;
             }
             return;
         }
 
-        let qvb_r: usize = self.query_vector_board(p, iof, itv) as usize;
-        let arr: [u32; 16] = if p == 1 {PATTERN_F[qvb_r + (1 << itv.length()) - 1]} else {PATTERN_S[qvb_r + (1 << itv.length()) - 1]};
+        let (mats, see) = self.line_pattern(p, iof, itv);
 
         let mut pack: F3Pack = F3Pack::new(if p == 0 {Color::Black} else {Color::White}, m, d, iof);
-        let mut form_f3: bool = false;
         let mut ind1: i32 = 0;
         let mut ind2: i32 = 0;
-        let mut ptr: usize = 0;
-        let mut ele: u32 = arr[ptr as usize];
-        let mut mat: u32;
-        mat = ele & 0xf;
-        ptr += 1;
-
-        while mat != MATERIAL_NONE {
-            ele >>= 4;
-            self.mat_inc[p as usize][mat] += 1;
-            self.score[self.piece_cnt][p as usize] += SCORE_HELPER[mat as usize];
-
-            if mat == Material::F3 as u32 {
-                form_f3 = true;
-            }
 
-            mat = ele & 0xf;
+        for (mat, n) in mats.iter().enumerate() {
+            self.mat_inc[p as usize][mat as u32] += n;
+            self.score[self.piece_cnt][p as usize] += SCORE_HELPER[mat] * *n as Score;
         }
 
-        if form_f3 && self.f3_stack[self.piece_cnt].iter().position(|x| *x == pack) == None {
+        if mats[Material::F3 as usize] > 0 && self.f3_stack[self.piece_cnt].iter().position(|x| *x == pack).is_none() {
             for i in itv.begin()..itv.end() {
-                if (arr[ptr] & ((1 as u32) << 2)) != 0 && ind1 < F3Pack::f4a_size {
+                let s: u32 = see[(i - itv.begin()) as usize];
+
+                if (s & (1u32 << 2)) != 0 && ind1 < F3Pack::F4A_SIZE {
                     pack.f4a[ind1 as usize] = m + D[d as usize] * (i - ion) as Move;
                     ind1 += 1;
                 }
 
-                if (arr[ptr] & ((1 as u32) << 25)) != 0 && ind2 < F3Pack::f3d_size {
+                if (s & (1u32 << 25)) != 0 && ind2 < F3Pack::F3D_SIZE {
                     pack.f3d[ind2 as usize] = m + D[d as usize] * (i - ion) as Move;
                     ind2 += 1;
                 }
 
-                if (arr[ptr] & ((1 as u32) << 24)) != 0 {
+                if (s & (1u32 << 24)) != 0 {
                     self.b4d_stack[self.piece_cnt] = m + D[d as usize] * (i - ion) as Move;
                 }
 
-                self.see[p as usize][iof as usize][i as usize] = arr[ptr];
-                ptr += 1;
+                self.see[p as usize][iof as usize][i as usize] = s;
             }
 
             self.f3_stack[self.piece_cnt].push(pack);
         } else {
             for i in itv.begin()..itv.end() {
-                if (arr[ptr] & ((1 as u32) << 24)) != 0 {
+                let s: u32 = see[(i - itv.begin()) as usize];
+
+                if (s & (1u32 << 24)) != 0 {
                     self.b4d_stack[self.piece_cnt] = m + D[d as usize] * (i - ion) as Move;
                 }
 
-                self.see[p as usize][iof as usize][i as usize] = arr[ptr];
-                ptr += 1;
+                self.see[p as usize][iof as usize][i as usize] = s;
             }
         }
     }
 
     fn line_update_dec(&mut self, p: i32, m: Move, d: Direction, itv: Interval) {
-        if itv.length() < 5 {
+        let iof: i32 = self.index_of(m, d);
+
+        if itv.length() < 5 || self.caro_dead(p, m, d, itv) || self.query_vector_board(p, iof, itv) == 0 {
             return;
         }
 
-        let tmp: usize = self.query_vector_board(p, self.index_of(m, d), itv) as usize;
+        let (mats, _) = self.line_pattern(p, iof, itv);
 
-        if tmp == 0 {
-            return;
+        for (mat, n) in mats.iter().enumerate() {
+            self.mat_inc[p as usize][mat as u32] -= n;
+            self.score[self.piece_cnt][p as usize] -= SCORE_HELPER[mat] * *n as Score;
         }
+    }
 
-        let mut ele: u32;
-        let mut mat: u32;
+    // Material counts and see bits of the line `itv` of `p`, the see bits from `itv.begin()` on.
+    // Lines longer than the tables are read through two windows of PATTERN_LINE_MAX squares, one from
+    // each end, overlapping by at least ten. Each square takes its see bits from the window it lies
+    // deeper in, and the materials are those of both windows less those of the overlap: a shape cut
+    // short by the inner end of a window is cut the same way in the overlap, which shares that end.
+    fn line_pattern(&self, p: i32, iof: i32, itv: Interval) -> ([i32; 10], [u32; BOARD_SIDE_MAX as usize]) {
+        let mut mats: [i32; 10] = [0; 10];
+        let mut see: [u32; BOARD_SIDE_MAX as usize] = [0; BOARD_SIDE_MAX as usize];
 
-        if p == 1 {
-            ele = PATTERN_F[tmp + (1 << itv.length()) - 1][0];
-        } else {
-            ele = PATTERN_S[tmp + (1 << itv.length()) - 1][0];
+        if itv.length() <= PATTERN_LINE_MAX {
+            let arr: &[u32; 16] = self.window(p, iof, itv);
+            add_materials(&mut mats, arr[0], 1);
+            see[..itv.length() as usize].copy_from_slice(&arr[1..itv.length() as usize + 1]);
+            return (mats, see);
         }
 
-        mat = ele & 0xf;
+        let left: Interval = Interval { begin_p: itv.begin(), end_p: itv.begin() + PATTERN_LINE_MAX };
+        let right: Interval = Interval { begin_p: itv.end() - PATTERN_LINE_MAX, end_p: itv.end() };
+        let (l, r) = (self.window(p, iof, left), self.window(p, iof, right));
+        let mid: i32 = (itv.begin() + itv.end()) / 2;
 
-        while mat != MATERIAL_NONE {
-            ele >>= 4;
-            self.mat_inc[p as usize][mat] -= 1;
-            self.score[self.piece_cnt][p as usize] -= SCORE_HELPER[mat as usize];
-            mat = ele & 0xf;
+        add_materials(&mut mats, l[0], 1);
+        add_materials(&mut mats, r[0], 1);
+        add_materials(&mut mats, self.window(p, iof, Interval { begin_p: right.begin(), end_p: left.end() })[0], -1);
+
+        for i in itv.begin()..itv.end() {
+            see[(i - itv.begin()) as usize] = if i < mid {l[(i - left.begin()) as usize + 1]} else {r[(i - right.begin()) as usize + 1]};
         }
+
+        (mats, see)
     }
 
-    unsafe fn f3packs_update(&mut self) {
+    // Table entry of a line of 5 to PATTERN_LINE_MAX squares
+    fn window(&self, p: i32, iof: i32, itv: Interval) -> &'static [u32; 16] {
+        self.pattern(p, self.query_vector_board(p, iof, itv) as usize + (1 << itv.length()) - 1)
+    }
+
+    fn f3packs_update(&mut self) {
         let mut f3_cnt: [i32; 2] = [0, 0];
         let mut it: usize = 0;
         while it != self.f3_stack[self.piece_cnt].len() {
             let mut f3p: F3Pack = self.f3_stack[self.piece_cnt][it];
 
-            if f3p.color == Color::Black {
-                f3p.update_renju();
+            if self.has_fouls(f3p.color) {
+                f3p.update_renju(self);
             } else {
-                f3p.update_free();
+                f3p.update_free(self);
             }
 
             self.f3_stack[self.piece_cnt][it] = f3p;
@@ -1659,9 +2021,6 @@ impl Board {
             } else {
                 let itp: usize = self.f3_stack[self.piece_cnt][it].color as usize;
                 f3_cnt[itp] += 1;
-                if self.f3_stack[self.piece_cnt][it].gen <= 0 {
-                    self.f3_formed_cnt[itp] += 1;
-                }
                 self.f3_stack[self.piece_cnt][it].gen += 1;
                 it += 1;
             }
@@ -1676,17 +2035,13 @@ impl Board {
         }
     }
 
-    unsafe fn update_material_see(&mut self, m: Move) {
+    fn update_material_see(&mut self, m: Move) {
         for i in 0..self.mat_inc.len() {
             for mat in Material::iter() {
                 self.mat_inc[i][mat] = 0;
             }
         }
 
-        for i in 0..self.f3_formed_cnt.len() {
-            self.f3_formed_cnt[i] = 0;
-        }
-
         self.mat[self.piece_cnt] = self.mat[self.piece_cnt - 1];
         self.score[self.piece_cnt] = self.score[self.piece_cnt - 1];
         self.f3_stack[self.piece_cnt] = self.f3_stack[self.piece_cnt - 1].clone();
@@ -1731,11 +2086,14 @@ impl Board {
         self.f3packs_update();
     }
 
-    pub unsafe fn do_move(&mut self, m: Move) {
-        if self.piece_cnt > 0 && !self.updated_interval[self.piece_cnt] {
+    pub fn do_move(&mut self, m: Move) {
+        if m == MOVE_PASS {
+            self.pass_cnt[self.piece_cnt] += 1;
             self.switch_side_to_move();
+            return;
+        }
+        if self.piece_cnt > 0 && !self.updated_interval[self.piece_cnt] {
             self.update_interval(self.last_move(1));
-            self.switch_side_to_move();
             self.updated_interval[self.piece_cnt] = true;
         }
         if self.piece_cnt > 0 && !self.updated_move_list[self.piece_cnt] {
@@ -1748,6 +2106,7 @@ impl Board {
         self.update_material_see(m);
         self.key ^= self.zobrists[self.side_to_move as usize][m as usize];
         self.switch_side_to_move();
+        self.pass_cnt[self.piece_cnt] = 0;
         self.updated_interval[self.piece_cnt] = false;
         self.updated_move_list[self.piece_cnt] = false;
     }
@@ -1797,6 +2156,11 @@ impl Board {
     pub fn undo_move(&mut self) {
         self.switch_side_to_move();
 
+        if self.pass_cnt[self.piece_cnt] > 0 {
+            self.pass_cnt[self.piece_cnt] -= 1;
+            return;
+        }
+
         self.piece_cnt -= 1;
         let last_move: Move = self.piece_list[self.piece_cnt];
         self._board[last_move as usize] = Color::Hide;
@@ -1813,19 +2177,11 @@ impl Board {
             }
         }
 
-        for i in 0..self.f3_formed_cnt.len() {
-            self.f3_formed_cnt[i] = 0;
-        }
-
         self.key ^= self.zobrists[self.side_to_move as usize][last_move as usize];
     }
 
     pub fn query(&self, color: Color, material: Material) -> i32 {
-        return self.mat[self.piece_cnt][color as usize][material];
-    }
-
-    fn query_inc(&self, color: Color, material: Material) -> i32 {
-        return self.mat_inc[color as usize][material];
+        self.mat[self.piece_cnt][color as usize][material]
     }
 
     fn index_of(&self, m: Move, d: Direction) -> i32 {
@@ -1847,57 +2203,81 @@ impl Board {
             ret += self.see_table[get_bits(self.see[self.side_to_move as usize][self.index_of(m, d) as usize][self.index_on(m, d) as usize], 3, 17) as usize] - self.see_table[get_bits(self.see[self.oppo_to_move as usize][self.index_of(m, d) as usize][self.index_on(m, d) as usize], 17, 31) as usize];
         }
     
-        return ret;
+        ret
     }
 
     fn query_us_inc(&self, color: Color, m: Move, material: Material) -> i32 {
         let mut ret: i32 = 0;
 
         for d in Direction::iter() {
-            ret += if self.query_see(color, self.index_of(m, d), self.index_on(m, d), (1 as u32) << material as u32) {1} else {0};
+            ret += if self.query_see(color, self.index_of(m, d), self.index_on(m, d), 1u32 << material as u32) {1} else {0};
         }
     
-        return ret;
+        ret
     }
 
     fn query_vcf(&self, color: Color, m: Move) -> i32 {
         let mut ret: i32 = 0;
     
         for d in Direction::iter() {
-            ret += if self.query_see(color, self.index_of(m, d), self.index_on(m, d), (1 as u32) << 31) {1} else {0};
+            ret += if self.query_see(color, self.index_of(m, d), self.index_on(m, d), 1u32 << 31) {1} else {0};
         }
     
-        return ret;
+        ret
     }
 
     fn query_opp_dec(&self, color: Color, m: Move, material: Material) -> i32 {
         let mut ret: i32 = 0;
 
         for d in Direction::iter() {
-            ret += if self.query_see(if color == Color::White {Color::Black} else {Color::White}, self.index_of(m, d), self.index_on(m, d), (1 as u32) << (material as i32 + 21)) {1} else {0};
+            ret += if self.query_see(if color == Color::White {Color::Black} else {Color::White}, self.index_of(m, d), self.index_on(m, d), 1u32 << (material as i32 + 21)) {1} else {0};
         }
 
-        return ret;
+        ret
     }
 
     pub fn check_wld_already(&self) -> Color {
-        if self.query(Color::Black, Material::C5) > 0 {
-            return Color::Black;
-        }
+        if self.rule == Rule::Caro {
+            // Blocked fives stay on the board, so only the last move can have won
+            if self.piece_cnt > 0 && self.caro_five(self.get_color(&self.last_move(1)), self.last_move(1)) {
+                return self.get_color(&self.last_move(1));
+            }
+        } else {
+            if self.query(Color::Black, Material::C5) > 0 {
+                return Color::Black;
+            }
 
-        if self.query(Color::White, Material::C5) > 0 {
-            return Color::White;
+            if self.query(Color::White, Material::C5) > 0 {
+                return Color::White;
+            }
         }
 
-        if self.piece_cnt as i32 >= MOVE_SIZE {
+        // A full board, or both players passing in a row
+        if self.piece_cnt as i32 >= self.side * self.side || self.pass_cnt[self.piece_cnt] >= 2 {
             return Color::Hide;
         }
 
-        if self.query(Color::Black, Material::C6) > 0 || self.query_inc(Color::Black, Material::F4) + self.query_inc(Color::Black, Material::B4) >= 2 || self.f3_formed_cnt[0 as usize] >= 2 {
+        // The exact rules judge every black move; the pattern counts miss threes that are only fouls deeper down
+        if self.has_fouls(Color::Black) && self.piece_cnt > self.setup_cnt && self.get_color(&self.last_move(1)) == Color::Black && self.last_foul() {
             return Color::White;
         }
 
-        return Color::None;
+        Color::None
+    }
+
+    // Like `check_wld_already`, with the five, the foul or the draw behind the result
+    pub fn game_end(&self) -> Option<GameEnd> {
+        let (result, five, foul, draw) = match self.check_wld_already() {
+            Color::Black => (1, self.five_of(Color::Black), None, None),
+            Color::White => match self.five_of(Color::White) {
+                Some(five) => (2, Some(five), None, None),
+                None => (2, None, self.foul_at(self.last_move(1)), None),
+            },
+            Color::Hide => (3, None, None, Some(if self.pass_cnt[self.piece_cnt] >= 2 {DrawReason::BothPassed} else {DrawReason::BoardFull})),
+            _ => return None,
+        };
+
+        Some(GameEnd { result, five, foul, draw })
     }
 
     pub fn check_wld(&self, offset: &mut Score) -> Color {
@@ -1926,8 +2306,7 @@ impl Board {
             return self.side_to_move;
         }
     
-        return Color::None;
-
+        Color::None
     }
 
     pub fn last_move(&self, n: usize) -> Move {
@@ -1946,19 +2325,11 @@ impl Board {
     pub fn is_quiet(&self, m: Option<Move>) -> bool {
         match m {
             Some(m) => {
-                if self.query_us_inc(self.side_to_move, m, Material::B4) + self.query_us_inc(self.side_to_move, m, Material::F3) != 0{
-                    false
-                } else {
-                    true
-                }
+                self.query_us_inc(self.side_to_move, m, Material::B4) + self.query_us_inc(self.side_to_move, m, Material::F3) == 0
             },
             None => {
-                if self.query(Color::Black, Material::B4) + self.query(Color::Black, Material::F3)
-                    + self.query(Color::White, Material::B4) + self.query(Color::White, Material::F3) != 0 {
-                    false
-                } else {
-                    true
-                }
+                self.query(Color::Black, Material::B4) + self.query(Color::Black, Material::F3)
+                    + self.query(Color::White, Material::B4) + self.query(Color::White, Material::F3) == 0
             }
         }
     }
@@ -1978,23 +2349,77 @@ impl Board {
         self._board[m as usize] == Color::Hide
     }
 
-    pub unsafe fn is_foul(&mut self, m: Move) -> bool {
-        let mut ret: bool = false;
-        let need_to_switch = self.side_to_move != Color::Black;
+    // Colour of the stone on `m`, Hide when the square is empty
+    pub fn stone(&self, m: Move) -> Color {
+        self.get_color(&m)
+    }
 
-        if need_to_switch {
-            self.switch_side_to_move();
+    // Why the side to move may not play `m` in a recorded game, if it may not
+    pub fn check_move(&self, m: Move) -> Result<(), String> {
+        if self.check_wld_already() != Color::None {
+            return Err("played after the end of the game".to_string());
+        }
+        if m == MOVE_PASS {
+            return Ok(());
+        }
+        if !self.contains(m) || !self.is_empty(m) {
+            return Err("not on an empty square".to_string());
+        }
+        if self.side_to_move == Color::Black && self.is_foul(m) {
+            return Err("a foul for Black".to_string());
         }
-        
-        self.do_move(m);
-        ret = self.query(Color::Black, Material::C6) > 0 || self.query_inc(Color::Black, Material::F4) + self.query_inc(Color::Black, Material::B4) >= 2 || self.f3_formed_cnt[Color::Black as usize] >= 2;
-        self.undo_move();
 
-        if need_to_switch {
-            self.switch_side_to_move();
+        Ok(())
+    }
+
+    pub fn is_foul(&self, m: Move) -> bool {
+        self.has_fouls(Color::Black) && self.is_empty(m) && self.renju_foul(m).is_some()
+    }
+
+    // Like `is_foul`, but tells why and which lines are involved
+    pub fn foul_of(&self, m: Move) -> Option<Foul> {
+        if !self.has_fouls(Color::Black) || !self.is_empty(m) {
+            return None;
         }
 
-        return ret;
+        self.foul_at(m)
+    }
+
+    // Foul report for a black stone on `m`, whether it is already there or not
+    fn foul_at(&self, m: Move) -> Option<Foul> {
+        let (reason, witnesses) = self.renju_foul(m)?;
+        let mut rc: RenjuCells = RenjuCells { cells: self._board };
+        rc.cells[m as usize] = Color::Black;
+
+        let lines: Vec<FoulLine> = witnesses.iter().map(|(d, e)| {
+            // The stones of the five, straight four or overline made on `e`, other than `e` and `m`
+            let stones: Vec<[i32; 2]> = rc.with(*e, |rc| {
+                let (lo, hi) = rc.run(*e, *d);
+                (0.._distance_between(lo, hi) + 1).map(|i| lo + D[*d as usize] * i as Move)
+                    .filter(|x| *x != *e && *x != m).map(|x| [_rank_of(x), _file_of(x)]).collect()
+            });
+
+            FoulLine {
+                direction: [_rank_of(m + D[*d as usize]) - _rank_of(m), _file_of(m + D[*d as usize]) - _file_of(m)],
+                stones,
+            }
+        }).collect();
+
+        Some(Foul { point: [_rank_of(m), _file_of(m)], reason, lines })
+    }
+
+    // Whether the last (black) stone broke the exact renju rules
+    fn last_foul(&self) -> bool {
+        let m: Move = self.last_move(1);
+        let mut rc: RenjuCells = RenjuCells { cells: self._board };
+        rc.foul(m).is_some()
+    }
+
+    // Exact renju verdict for Black playing the empty square `m`
+    fn renju_foul(&self, m: Move) -> Option<(FoulReason, Vec<(Direction, Move)>)> {
+        let mut rc: RenjuCells = RenjuCells { cells: self._board };
+        rc.cells[m as usize] = Color::Black;
+        rc.foul(m)
     }
 
     pub fn defend_b4(&self) -> Move {
@@ -2002,6 +2427,128 @@ impl Board {
     }
 }
 
+// Renju foul rules for Black read straight off a scratch copy of the stones. Fives, fours and overlines
+// are counted on the lines through a move; a three only counts if one of its straight-four points
+// is itself allowed, which is resolved recursively as the official rules define it.
+struct RenjuCells {
+    cells: [Color; MOVE_CAPACITY as usize],
+}
+
+impl RenjuCells {
+    // Runs `f` with a black stone on the empty square `m`
+    fn with<R, F: FnOnce(&mut RenjuCells) -> R>(&mut self, m: Move, f: F) -> R {
+        self.cells[m as usize] = Color::Black;
+        let r: R = f(self);
+        self.cells[m as usize] = Color::Hide;
+        r
+    }
+
+    // Ends of the black run along `d` through the black stone on `m`
+    fn run(&self, m: Move, d: Direction) -> (Move, Move) {
+        let step: Move = D[d as usize];
+        let mut lo: Move = m;
+        let mut hi: Move = m;
+
+        while self.cells[(lo - step) as usize] == Color::Black {
+            lo -= step;
+        }
+        while self.cells[(hi + step) as usize] == Color::Black {
+            hi += step;
+        }
+
+        (lo, hi)
+    }
+
+    fn run_length(&self, m: Move, d: Direction) -> i32 {
+        let (lo, hi) = self.run(m, d);
+        _distance_between(lo, hi) + 1
+    }
+
+    // First empty square from `m` towards `sign` past the black stones, if the line does not end first
+    fn next_empty(&self, m: Move, d: Direction, sign: Move) -> Option<Move> {
+        let step: Move = D[d as usize] * sign;
+        let mut x: Move = m + step;
+
+        while self.cells[x as usize] == Color::Black {
+            x += step;
+        }
+
+        if self.cells[x as usize] == Color::Hide {Some(x)} else {None}
+    }
+
+    // Squares completing an exact five with `m` along `d`; the two ends of a straight four count once
+    fn fours(&mut self, m: Move, d: Direction) -> Vec<Move> {
+        let mut ret: Vec<Move> = Vec::new();
+
+        for sign in [-1, 1] {
+            if let Some(e) = self.next_empty(m, d, sign) {
+                if self.with(e, |rc| rc.run_length(e, d) == 5) {
+                    ret.push(e);
+                }
+            }
+        }
+
+        if ret.len() == 2 && self.run_length(m, d) == 4 {
+            ret.truncate(1);
+        }
+
+        ret
+    }
+
+    fn is_straight_four(&mut self, m: Move, d: Direction) -> bool {
+        let (lo, hi) = self.run(m, d);
+        let step: Move = D[d as usize];
+
+        _distance_between(lo, hi) + 1 == 4
+            && [lo - step, hi + step].iter().all(|e| self.cells[*e as usize] == Color::Hide && self.with(*e, |rc| rc.run_length(*e, d) == 5))
+    }
+
+    // The allowed straight-four point, if the stone on `m` makes a real three along `d`
+    fn three(&mut self, m: Move, d: Direction) -> Option<Move> {
+        for sign in [-1, 1] {
+            if let Some(e) = self.next_empty(m, d, sign) {
+                if self.with(e, |rc| rc.is_straight_four(e, d) && rc.foul(e).is_none()) {
+                    return Some(e);
+                }
+            }
+        }
+
+        None
+    }
+
+    // Verdict for the black stone on `m`, with the line and the square that shows each offending shape
+    fn foul(&mut self, m: Move) -> Option<(FoulReason, Vec<(Direction, Move)>)> {
+        if Direction::iter().any(|d| self.run_length(m, d) == 5) {
+            return None;
+        }
+
+        let overlines: Vec<(Direction, Move)> = Direction::iter().filter(|d| self.run_length(m, *d) > 5).map(|d| (d, m)).collect();
+        if !overlines.is_empty() {
+            return Some((FoulReason::Overline, overlines));
+        }
+
+        let mut fours: Vec<(Direction, Move)> = Vec::new();
+        for d in Direction::iter() {
+            fours.extend(self.fours(m, d).into_iter().map(|e| (d, e)));
+        }
+        if fours.len() >= 2 {
+            return Some((FoulReason::DoubleFour, fours));
+        }
+
+        let mut threes: Vec<(Direction, Move)> = Vec::new();
+        for d in Direction::iter() {
+            if let Some(e) = self.three(m, d) {
+                threes.push((d, e));
+            }
+        }
+        if threes.len() >= 2 {
+            return Some((FoulReason::DoubleThree, threes));
+        }
+
+        None
+    }
+}
+
 use std::cmp;
 
 pub fn _rank_of(m: Move) -> i32 {
@@ -2012,60 +2559,60 @@ pub fn _file_of(m: Move) -> i32 {
     (m as i32 & ((1 << BOARD_SIDE_BIT) - 1)) - BOARD_BOUNDARY
 }
 
-fn _mdiag_of(m: Move) -> i32 {
-    let s = _start_of(m, Direction::DMDiag);
+fn _mdiag_of(m: Move, side: i32) -> i32 {
+    let s = _start_of(m, Direction::DMDiag, side);
     let r = _rank_of(s);
 
     if r == 0 {
         _file_of(s)
     } else {
-        r + BOARD_SIDE - 1
+        r + side - 1
     }
 }
 
-fn _adiag_of(m: Move) -> i32 {
-    let s = _start_of(m, Direction::DADiag);
+fn _adiag_of(m: Move, side: i32) -> i32 {
+    let s = _start_of(m, Direction::DADiag, side);
     let r = _rank_of(s);
 
     if r == 0 {
         _file_of(s)
     } else {
-        r + BOARD_SIDE - 1
+        r + side - 1
     }
 }
 
-fn _mdiag_index_on(m: Move) -> i32 {
+fn _mdiag_index_on(m: Move, side: i32) -> i32 {
     let mut i = 1;
 
-    while is_ok_move(m - D[Direction::DMDiag as usize] * i) {
+    while is_ok_move_on(m - D[Direction::DMDiag as usize] * i, side) {
         i += 1;
     }
 
     (i - 1) as i32
 }
 
-fn _adiag_index_on(m: Move) -> i32 {
+fn _adiag_index_on(m: Move, side: i32) -> i32 {
     let mut i = 1;
 
-    while is_ok_move(m - D[Direction::DADiag as usize] * i) {
+    while is_ok_move_on(m - D[Direction::DADiag as usize] * i, side) {
         i += 1;
     }
 
     (i - 1) as i32
 }
 
-fn _start_of(m: Move, d: Direction) -> Move {
+fn _start_of(m: Move, d: Direction, side: i32) -> Move {
     let mut i = 1;
-    while is_ok_move(m - D[d as usize] * i) {
+    while is_ok_move_on(m - D[d as usize] * i, side) {
         i += 1;
     }
 
     m - D[d as usize] * (i - 1)
 }
 
-fn _end_of(m: Move, d: Direction) -> Move {
+fn _end_of(m: Move, d: Direction, side: i32) -> Move {
     let mut i = 1;
-    while is_ok_move(m + D[d as usize] * i) {
+    while is_ok_move_on(m + D[d as usize] * i, side) {
         i += 1;
     }
 
@@ -2076,6 +2623,40 @@ pub fn _make_move(r: &i32, f: &i32) -> Move {
     (((r + BOARD_BOUNDARY) << BOARD_SIDE_BIT) + f + BOARD_BOUNDARY) as Move
 }
 
+// Orders the stones of a set-up position as a game, passing whenever a colour has to move twice,
+// so that `side_to_move` is to move after them. Never passes twice in a row.
+pub fn _setup_moves(black: &[Move], white: &[Move], side_to_move: Color) -> Vec<Move> {
+    let mut moves: Vec<Move> = Vec::new();
+    let mut turn: Color = Color::Black;
+    let (mut b, mut w) = (black.iter(), white.iter());
+
+    while moves.iter().filter(|m| **m != MOVE_PASS).count() < black.len() + white.len() {
+        let next: Option<&Move> = if turn == Color::Black {b.next()} else {w.next()};
+        moves.push(*next.unwrap_or(&MOVE_PASS));
+        turn = if turn == Color::Black {Color::White} else {Color::Black};
+    }
+
+    if turn != side_to_move {
+        if moves.last() == Some(&MOVE_PASS) {
+            moves.pop();
+        } else {
+            moves.push(MOVE_PASS);
+        }
+    }
+
+    moves
+}
+
+// Adds `sign` for each material packed four bits apiece into `ele`, up to MATERIAL_NONE
+fn add_materials(mats: &mut [i32; 10], ele: u32, sign: i32) {
+    let mut ele: u32 = ele;
+
+    while ele & 0xf != MATERIAL_NONE {
+        mats[(ele & 0xf) as usize] += sign;
+        ele >>= 4;
+    }
+}
+
 fn _distance_between(m1: Move, m2: Move) -> i32 {
     cmp::max((_rank_of(m1) - _rank_of(m2)).abs(), (_file_of(m1) - _file_of(m2)).abs())
 }
@@ -2096,6 +2677,534 @@ impl PRNG {
         self.s ^= self.s << 25;
         self.s ^= self.s >> 27;
         
-        self.s * (2685821657736338717 as u64)
+        self.s.wrapping_mul(2685821657736338717u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(r: i32, files: &[i32]) -> Vec<Move> {
+        files.iter().map(|f| _make_move(&r, f)).collect()
+    }
+
+    fn board(rule: Rule, side: i32, black: &[Move], white: &[Move], side_to_move: Color) -> Board {
+        let mut bd: Board = Board::new();
+        bd.set_rule(rule);
+        assert!(bd.set_side(side));
+        assert!(bd.setup(black, white, side_to_move));
+        bd
+    }
+
+    fn caro_board(black: &[i32], white: &[i32]) -> Board {
+        let mut bd: Board = Board::new();
+        bd.set_rule(Rule::Caro);
+        assert!(bd.setup(&row(7, black), &row(7, white), Color::Black));
+        bd
+    }
+
+    #[test]
+    fn caro_five_blocked_at_both_ends_does_not_win() {
+        let mut bd: Board = caro_board(&[4, 5, 6, 7], &[3, 9]);
+        let m: Move = _make_move(&7, &8);
+
+        assert_eq!(bd.query(Color::Black, Material::B4), 0);
+        assert!(!bd.is_winning_move(m));
+        bd.do_move(m);
+        assert!(bd.check_wld_already() == Color::None);
+        assert_eq!(bd.query(Color::Black, Material::C5), 0);
+    }
+
+    #[test]
+    fn caro_five_open_at_one_end_wins() {
+        let mut bd: Board = caro_board(&[4, 5, 6, 7], &[3, 10]);
+        let m: Move = _make_move(&7, &8);
+
+        assert!(bd.is_winning_move(m));
+        bd.do_move(m);
+        assert!(bd.check_wld_already() == Color::Black);
+    }
+
+    #[test]
+    fn caro_lines_shut_in_at_five_are_dead() {
+        // Whether the black line through the centre along row 7 is skipped, and its length
+        let dead = |black: &[i32], white: &[i32]| -> (bool, i32) {
+            let bd: Board = caro_board(black, white);
+            let m: Move = _make_move(&7, &7);
+            let d: Direction = Direction::iter().find(|d| _rank_of(m + D[*d as usize]) == 7).unwrap();
+            let itv: Interval = bd.interval[0][bd.index_of(m, d) as usize][bd.index_on(m, d) as usize];
+            (bd.caro_dead(0, m, d, itv), itv.length())
+        };
+
+        for black in [&[5, 6, 7][..], &[4, 6, 8], &[4, 5, 6, 7]] {
+            assert_eq!(dead(black, &[3, 9]), (true, 5), "{:?}", black);
+        }
+
+        // One square more leaves room for a five open at one end, unless it is all but full
+        assert_eq!(dead(&[5, 6, 7], &[3, 10]), (false, 6));
+        assert_eq!(dead(&[4, 5, 7, 8, 9], &[3, 10]), (true, 6));
+    }
+
+    #[test]
+    fn long_line_keeps_a_five_across_the_middle() {
+        let bd: Board = board(Rule::Freestyle, 20, &row(7, &[5, 6, 8, 9, 10, 11, 12, 14]), &[], Color::White);
+
+        assert_eq!(bd.query(Color::Black, Material::C5), 1);
+        assert!(bd.check_wld_already() == Color::Black);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn long_line_keeps_a_straight_four_across_the_middle() {
+        let bd: Board = board(Rule::Freestyle, 20, &row(7, &[3, 8, 9, 10, 11, 16]), &[], Color::White);
+
+        assert_eq!(bd.query(Color::Black, Material::F4), 1);
+        assert_eq!(bd.query(Color::Black, Material::B4), 0);
+    }
+
+    #[test]
+    fn fives_at_each_edge_win() {
+        for side in [15, 16, 20] {
+            let column = |r: &[i32]| -> Vec<Move> { r.iter().map(|r| _make_move(r, &7)).collect() };
+            let fives: Vec<Vec<Move>> = vec![
+                row(7, &[0, 1, 2, 3, 4]),
+                row(7, &[side - 5, side - 4, side - 3, side - 2, side - 1]),
+                column(&[0, 1, 2, 3, 4]),
+                column(&[side - 5, side - 4, side - 3, side - 2, side - 1]),
+            ];
+
+            for five in fives.iter() {
+                let mut bd: Board = board(Rule::Standard, side, &five[..4], &[], Color::Black);
+                bd.do_move(five[4]);
+                assert_eq!(bd.query(Color::Black, Material::C5), 1, "{}x{}", side, side);
+                assert!(bd.check_wld_already() == Color::Black);
+            }
+        }
+    }
+
+    #[test]
+    fn set_up_stones_are_not_fouls() {
+        let black: Vec<Move> = vec![_make_move(&5, &7), _make_move(&6, &7), _make_move(&7, &5), _make_move(&7, &6), _make_move(&7, &7)];
+        let white: Vec<Move> = vec![_make_move(&0, &0), _make_move(&0, &2), _make_move(&14, &0), _make_move(&14, &2)];
+        let mut bd: Board = board(Rule::Renju, 15, &black, &white, Color::White);
+
+        assert!(bd.check_wld_already() == Color::None);
+        bd.do_move(_make_move(&0, &4));
+        bd.undo_move();
+        assert!(bd.check_wld_already() == Color::None);
+
+        // The same double three played after the set-up stones loses
+        let mut bd: Board = board(Rule::Renju, 15, &black[..4], &white, Color::Black);
+        bd.do_move(black[4]);
+        assert!(bd.check_wld_already() == Color::White);
+    }
+
+    // Renju foul verdicts; x black, o white, * the point Black wants to play
+    struct FoulCase {
+        name: &'static str,
+        rows: [&'static str; 15],
+        expected: Option<FoulReason>,
+    }
+
+    const FOUL_CASES: [FoulCase; 17] = [
+        FoulCase {
+            name: "plain double three",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                ".......x.......",
+                ".......x.......",
+                ".....xx*.......",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: Some(FoulReason::DoubleThree),
+        },
+        FoulCase {
+            name: "four-three is allowed",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                ".......x.......",
+                ".......x.......",
+                "....xxx*.......",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: None,
+        },
+        FoulCase {
+            name: "double four on two lines",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                ".......x.......",
+                ".......x.......",
+                ".......x.......",
+                "....xxx*.......",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: Some(FoulReason::DoubleFour),
+        },
+        FoulCase {
+            name: "double four on one line",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                ".xxx.*.xxx.....",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: Some(FoulReason::DoubleFour),
+        },
+        FoulCase {
+            name: "overline",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "..xxx*xx.......",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: Some(FoulReason::Overline),
+        },
+        FoulCase {
+            name: "five beats a double three",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                ".....x.x.......",
+                ".....xx........",
+                "...xx*xx.......",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: None,
+        },
+        FoulCase {
+            name: "three blocked by white",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                ".......x.......",
+                ".......x.......",
+                "....oxx*.......",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: None,
+        },
+        FoulCase {
+            name: "split three",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                ".......x.......",
+                ".......x.......",
+                "....x.x*.......",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: Some(FoulReason::DoubleThree),
+        },
+        FoulCase {
+            name: "three spoiled by an overline",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                ".....x.........",
+                ".....x.........",
+                ".o.xx*..x......",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: None,
+        },
+        FoulCase {
+            name: "three whose four point is a double three",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "........x......",
+                "........x......",
+                "...o.xx*.......",
+                ".......x.x.....",
+                ".......x..x....",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: None,
+        },
+        FoulCase {
+            name: "three whose four point is allowed",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "........x......",
+                "........x......",
+                "...o.xx*.......",
+                ".......x.x.....",
+                ".......x.......",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: Some(FoulReason::DoubleThree),
+        },
+        FoulCase {
+            name: "three whose four point makes four-three-three",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                ".......x.......",
+                ".......x.......",
+                "...o.xx*.......",
+                "........xx.....",
+                "........x......",
+                "...............",
+                "........o......",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: None,
+        },
+        FoulCase {
+            name: "three whose four point has a three with a double four point",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                ".......x.......",
+                ".......x.xx....",
+                "...o.xx*.......",
+                "........xx.....",
+                "........x......",
+                "...............",
+                "........o......",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: Some(FoulReason::DoubleThree),
+        },
+        FoulCase {
+            name: "three whose four point is an overline",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                ".......xx......",
+                ".......xx......",
+                "...o.xx*.......",
+                "........x......",
+                "........x......",
+                "........x......",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: None,
+        },
+        FoulCase {
+            name: "five beats an overline",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                ".......x.......",
+                ".......x.......",
+                ".......x.......",
+                "...xxxx*.......",
+                ".......x.......",
+                ".......x.......",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: None,
+        },
+        FoulCase {
+            name: "five beats a double four",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "....x..x.......",
+                ".....x.x.......",
+                "......xx.......",
+                "...xxxx*.......",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: None,
+        },
+        FoulCase {
+            name: "overline with a double three",
+            rows: [
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                ".....x.x.......",
+                "......xx.......",
+                "...xxxx*x......",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+                "...............",
+            ],
+            expected: Some(FoulReason::Overline),
+        },
+    ];
+
+    fn diagram(rows: &[&str]) -> (Board, Move) {
+        let mut black: Vec<Move> = Vec::new();
+        let mut white: Vec<Move> = Vec::new();
+        let mut point: Move = MOVE_NONE;
+
+        for (r, row) in rows.iter().enumerate() {
+            for (f, c) in row.chars().enumerate() {
+                let m: Move = _make_move(&(r as i32), &(f as i32));
+                match c {
+                    'x' => black.push(m),
+                    'o' => white.push(m),
+                    '*' => point = m,
+                    _ => {}
+                }
+            }
+        }
+
+        (board(Rule::Renju, 15, &black, &white, Color::Black), point)
+    }
+
+    #[test]
+    fn renju_fouls() {
+        for case in FOUL_CASES.iter() {
+            let (bd, point) = diagram(&case.rows);
+
+            assert_eq!(bd.foul_of(point).map(|f| f.reason), case.expected, "{}", case.name);
+            assert_eq!(bd.is_foul(point), case.expected.is_some(), "{}", case.name);
+        }
+    }
+
+    #[test]
+    fn game_end_agrees_with_is_foul() {
+        for case in FOUL_CASES.iter() {
+            let (mut bd, point) = diagram(&case.rows);
+            let foul: bool = bd.is_foul(point);
+
+            bd.do_move(point);
+            assert_eq!(bd.game_end().and_then(|e| e.foul).map(|f| f.reason), case.expected, "{}", case.name);
+            assert_eq!(bd.check_wld_already() == Color::White, foul, "{}", case.name);
+        }
+    }
+}